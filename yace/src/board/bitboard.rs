@@ -9,6 +9,7 @@ pub const BLACK: Color = true;
 pub type Bitboard = u64;
 
 pub const EMPTY: Bitboard = 0;
+#[allow(dead_code)]
 pub const FULL: Bitboard = !EMPTY;
 
 pub const RANK1: Bitboard = 0xff;
@@ -31,6 +32,9 @@ pub const FILEG: Bitboard = FILEA << 6;
 pub const FILEH: Bitboard = FILEA << 7;
 pub const FILES: [Bitboard; 8] = [FILEA, FILEB, FILEC, FILED, FILEE, FILEF, FILEG, FILEH];
 
+pub const LIGHT_SQUARES: Bitboard = 0x55aa55aa55aa55aa;
+pub const DARK_SQUARES: Bitboard = !LIGHT_SQUARES;
+
 pub trait BitboardExt {
     fn set(self, pos: Square) -> Self;
     fn unset(self, pos: Square) -> Self;
@@ -42,6 +46,7 @@ pub trait BitboardExt {
     fn forward<const COLOR: bool>(self) -> Self;
     fn forward_left<const COLOR: bool>(self) -> Self;
     fn forward_right<const COLOR: bool>(self) -> Self;
+    #[allow(dead_code)]
     fn display(self);
 }
 
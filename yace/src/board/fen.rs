@@ -1,24 +1,91 @@
+use std::fmt::Display;
+
 use super::*;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenError {
+    MissingField(&'static str),
+    TooManyFields,
+    InvalidRankCount(usize),
+    // Ranks are numbered from 1 to 8
+    InvalidRankLength(i8),
+    InvalidPieceChar(char),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    InvalidKingCount(Color, u32),
+    PawnOnBackRank(Square),
+    // Castling right given without the king and rook on their starting squares
+    InconsistentCastling(char),
+    // En passant square without a pawn that just made a double push
+    ImplausibleEnPassant(String),
+    OpponentInCheck,
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {field} field"),
+            FenError::TooManyFields => write!(f, "too many fields"),
+            FenError::InvalidRankCount(count) => write!(f, "expected 8 ranks, found {count}"),
+            FenError::InvalidRankLength(rank) => write!(f, "rank {rank} doesn't have 8 squares"),
+            FenError::InvalidPieceChar(c) => write!(f, "invalid piece char '{c}'"),
+            FenError::InvalidSideToMove(side) => write!(f, "invalid side to move \"{side}\""),
+            FenError::InvalidCastling(castling) => write!(f, "invalid castling rights \"{castling}\""),
+            FenError::InvalidEnPassant(ep) => write!(f, "invalid en passant square \"{ep}\""),
+            FenError::InvalidHalfmoveClock(clock) => write!(f, "invalid halfmove clock \"{clock}\""),
+            FenError::InvalidFullmoveNumber(number) => write!(f, "invalid fullmove number \"{number}\""),
+            FenError::InvalidKingCount(color, count) => write!(f, "{} has {count} kings", if *color == WHITE {"white"} else {"black"}),
+            FenError::PawnOnBackRank(sq) => write!(f, "pawn on back rank square {}", sq.name()),
+            FenError::InconsistentCastling(c) => write!(f, "castling right '{c}' without king and rook on their starting squares"),
+            FenError::ImplausibleEnPassant(ep) => write!(f, "no pawn could have just made a double push for en passant square {ep}"),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
 impl Board {
-    pub fn from_fen(fen_string: &str) -> Option<Self> {
+    pub fn from_fen(fen_string: &str) -> Result<Self, FenError> {
         let mut parts = fen_string.split_ascii_whitespace();
-        let pieces = parts.next().unwrap();
-        let to_move = parts.next().unwrap();
-        let castling = parts.next().unwrap();
-        let en_passant = parts.next().unwrap();
-        //let _halfmove: &str = parts.next().unwrap();
-        //let _fullmove = parts.next().unwrap();
+        let pieces = parts.next().ok_or(FenError::MissingField("piece placement"))?;
+        let to_move = parts.next().ok_or(FenError::MissingField("side to move"))?;
+        let castling = parts.next().ok_or(FenError::MissingField("castling"))?;
+        let en_passant = parts.next().ok_or(FenError::MissingField("en passant"))?;
+        // Counters are optional as some tools only give the first four fields
+        let halfmove = parts.next().map_or(Ok(0), |halfmove| halfmove.parse().map_err(|_| FenError::InvalidHalfmoveClock(halfmove.to_string())))?;
+        let fullmove = parts.next().map_or(Ok(1), |fullmove| match fullmove.parse() {
+            Ok(fullmove) if fullmove > 0 => Ok(fullmove),
+            _ => Err(FenError::InvalidFullmoveNumber(fullmove.to_string())),
+        })?;
+        if parts.next().is_some() {
+            return Err(FenError::TooManyFields);
+        }
 
         let mut board = Board::empty();
 
-        for (rank, line) in (0..8).rev().zip(pieces.split('/')) {
+        let ranks: Vec<&str> = pieces.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::InvalidRankCount(ranks.len()));
+        }
+        for (rank, line) in (0..8).rev().zip(ranks) {
             let mut file = 0;
+            let mut previous_digit = false;
             for char in line.chars() {
-                if let Some(n) = char.to_digit(10) {
+                if file >= 8 {
+                    return Err(FenError::InvalidRankLength(rank + 1));
+                }
+                if let Some(n @ 1..=8) = char.to_digit(10) {
+                    // Empty squares must be merged in a single digit
+                    if previous_digit {
+                        return Err(FenError::InvalidRankLength(rank + 1));
+                    }
+                    previous_digit = true;
                     file += n as i8;
                 } else {
-                    let color = if char.is_uppercase() {WHITE} else {BLACK};
+                    previous_digit = false;
+                    let color = if char.is_ascii_uppercase() {WHITE} else {BLACK};
                     let piece = match char.to_ascii_uppercase() {
                         'P' => PAWN,
                         'N' => KNIGHT,
@@ -26,42 +93,142 @@ impl Board {
                         'R' => ROOK,
                         'Q' => QUEEN,
                         'K' => KING,
-                        _ => panic!("Invalid piece char")
+                        _ => return Err(FenError::InvalidPieceChar(char)),
                     };
                     board.add_piece(piece, Square::new(file, rank), color);
                     file += 1;
                 }
             }
+            if file != 8 {
+                return Err(FenError::InvalidRankLength(rank + 1));
+            }
         }
 
-        if to_move == "b" {
-            board.to_move = BLACK;
+        for color in [WHITE, BLACK] {
+            let king_count = (board.bitboards[KING] & board.pieces[color]).count_ones();
+            if king_count != 1 {
+                return Err(FenError::InvalidKingCount(color, king_count));
+            }
         }
-
-        let mut castling_rights = CastlingRights::new();
-        if !castling.contains('K') {
-            castling_rights.remove(WHITE, KINGSIDE);
+        let back_rank_pawns = board.bitboards[PAWN] & (RANK1 | RANK8);
+        if back_rank_pawns != EMPTY {
+            return Err(FenError::PawnOnBackRank(back_rank_pawns.lsb()));
         }
-        if !castling.contains('Q') {
-            castling_rights.remove(WHITE, QUEENSIDE);
+
+        board.to_move = match to_move {
+            "w" => WHITE,
+            "b" => BLACK,
+            _ => return Err(FenError::InvalidSideToMove(to_move.to_string())),
+        };
+        if board.to_move == BLACK {
+            board.zobrist_hash.handle_side_to_move();
         }
-        if !castling.contains('k') {
-            castling_rights.remove(BLACK, KINGSIDE);
+
+        let mut castling_rights = CastlingRights::new();
+        let rights = [(WHITE, KINGSIDE, 'K'), (WHITE, QUEENSIDE, 'Q'), (BLACK, KINGSIDE, 'k'), (BLACK, QUEENSIDE, 'q')];
+        if castling != "-" {
+            // Each right appears at most once and in the standard order
+            let mut expected = rights.iter().map(|&(_, _, c)| c);
+            for c in castling.chars() {
+                if !expected.any(|right| right == c) {
+                    return Err(FenError::InvalidCastling(castling.to_string()));
+                }
+            }
         }
-        if !castling.contains('q') {
-            castling_rights.remove(BLACK, QUEENSIDE);
+        for (color, side, c) in rights {
+            if !castling.contains(c) {
+                castling_rights.remove(color, side);
+                continue;
+            }
+            let king_start = if color == WHITE {E1} else {E8};
+            let rook_start = ROOK_CASTLING_START[CastlingRights::index(color, side)];
+            if board.squares[king_start as usize] != Some(KING) || !board.pieces[color].has(king_start)
+            || board.squares[rook_start as usize] != Some(ROOK) || !board.pieces[color].has(rook_start) {
+                return Err(FenError::InconsistentCastling(c));
+            }
         }
+        board.zobrist_hash.handle_castling(board.castling_rights);
         board.castling_rights = castling_rights;
+        board.zobrist_hash.handle_castling(board.castling_rights);
 
+        // FEN gives the square behind the pawn but we store the pawn square itself
         if en_passant != "-" {
-            let mut chars = en_passant.chars();
-            let file = chars.next().unwrap();
-            let rank = chars.next().unwrap();
-            let square = square_from_name(file as i8 - 'a' as i8, rank.to_digit(10).unwrap() as Square);
-            board.ep_target = Some(square);
+            let square = square_from_str(en_passant).ok_or_else(|| FenError::InvalidEnPassant(en_passant.to_string()))?;
+            let (pawn_square, start_square, pawn_color) = match (square.rank(), board.to_move) {
+                (2, BLACK) => (square.forward::<WHITE>(), square.backward::<WHITE>(), WHITE),
+                (5, WHITE) => (square.forward::<BLACK>(), square.backward::<BLACK>(), BLACK),
+                _ => return Err(FenError::InvalidEnPassant(en_passant.to_string())),
+            };
+            if board.squares[pawn_square as usize] != Some(PAWN) || !board.pieces[pawn_color].has(pawn_square)
+            || board.squares[square as usize].is_some() || board.squares[start_square as usize].is_some() {
+                return Err(FenError::ImplausibleEnPassant(en_passant.to_string()));
+            }
+            board.ep_target = Some(pawn_square);
+            board.zobrist_hash.handle_ep(board.ep_target);
+        }
+
+        board.halfmove_clock = halfmove;
+        board.fullmove_number = fullmove;
+
+        let opponent_in_check = if board.to_move == WHITE {
+            board.checkers::<BLACK>() != EMPTY
+        } else {
+            board.checkers::<WHITE>() != EMPTY
+        };
+        if opponent_in_check {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        Ok(board)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::with_capacity(90);
+
+        for rank in RANK_LIST.into_iter().rev() {
+            let mut empty = 0;
+            for file in FILE_LIST {
+                let square = square_from_name(file, rank);
+                if let Some(piece) = self.squares[square as usize] {
+                    if empty > 0 {
+                        fen.push(char::from_digit(empty, 10).unwrap());
+                        empty = 0;
+                    }
+                    if self.pieces[WHITE].has(square) {
+                        fen.push(char::from(piece).to_ascii_uppercase());
+                    } else {
+                        fen.push(char::from(piece));
+                    }
+                } else {
+                    empty += 1;
+                }
+            }
+            if empty > 0 {
+                fen.push(char::from_digit(empty, 10).unwrap());
+            }
+            if rank != 1 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(if self.to_move == WHITE {" w "} else {" b "});
+
+        for (color, side, c) in [(WHITE, KINGSIDE, 'K'), (WHITE, QUEENSIDE, 'Q'), (BLACK, KINGSIDE, 'k'), (BLACK, QUEENSIDE, 'q')] {
+            if self.castling_rights.has(color, side) {
+                fen.push(c);
+            }
+        }
+        if self.castling_rights == 0 {
+            fen.push('-');
         }
 
-        Some(board)
+        match self.ep_target {
+            Some(pawn_square) if pawn_square.rank() == 3 => fen = fen + " " + &pawn_square.backward::<WHITE>().name(),
+            Some(pawn_square) => fen = fen + " " + &pawn_square.backward::<BLACK>().name(),
+            None => fen.push_str(" -"),
+        }
+
+        fen + &format!(" {} {}", self.halfmove_clock, self.fullmove_number)
     }
 }
 
@@ -69,13 +236,94 @@ impl Board {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_fen_errors() {
+        let errors = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq", FenError::MissingField("en passant")),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 extra", FenError::TooManyFields),
+            ("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidRankCount(7)),
+            ("rnbqkbnr/pppppppp/8/8/8/7/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidRankLength(3)),
+            ("rnbqkbnr/pppppppp/8/8/8/53/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidRankLength(3)),
+            ("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPieceChar('x')),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", FenError::InvalidSideToMove("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1", FenError::InvalidCastling("KKkq".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1", FenError::InvalidEnPassant("e9".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1", FenError::InvalidEnPassant("e3".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1", FenError::ImplausibleEnPassant("e6".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1", FenError::InvalidHalfmoveClock("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", FenError::InvalidFullmoveNumber("0".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w kq - 0 1", FenError::InvalidKingCount(WHITE, 0)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBKKBNR w kq - 0 1", FenError::InvalidKingCount(WHITE, 2)),
+            ("rnbqkbnP/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNR w KQq - 0 1", FenError::PawnOnBackRank(H8)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1", FenError::InconsistentCastling('K')),
+            ("4k3/8/8/8/8/8/8/4K2R w q - 0 1", FenError::InconsistentCastling('q')),
+            ("4k3/8/8/8/8/8/8/K3R3 w - - 0 1", FenError::OpponentInCheck),
+        ];
+
+        for (fen, error) in errors {
+            assert_eq!(Board::from_fen(fen).err(), Some(error), "{fen}");
+        }
+    }
+
     #[test]
     fn test_fen() {
         let board = Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
 
-        assert_eq!(board.ep_target, Some(E3));
+        assert_eq!(board.ep_target, Some(E4));
 
         let board = Board::from_fen("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2 ").unwrap();
         assert_eq!(board.ep_target, None);
+        assert_eq!(board.halfmove_clock, 1);
+        assert_eq!(board.fullmove_number, 2);
+    }
+
+    #[test]
+    fn test_to_fen() {
+        assert_eq!(Board::new().to_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pp1p1ppp/8/2pPp3/8/8/PPP1PPPP/RNBQKBNR w KQkq e6 0 3",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 37 61",
+        ] {
+            assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+        }
+
+        // Missing counters default to 0 and 1
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6k w - -").unwrap().to_fen(), "8/8/8/8/8/8/8/K6k w - - 0 1");
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut board = Board::new();
+        let mut ext_moves = Vec::new();
+        for (uci_move, fen) in [
+            ("g1f3", "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1"),
+            ("b8c6", "r1bqkbnr/pppppppp/2n5/8/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 2 2"),
+            ("e2e4", "r1bqkbnr/pppppppp/2n5/8/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq e3 0 2"),
+            ("c6d4", "r1bqkbnr/pppppppp/8/8/3nP3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 1 3"),
+            ("f3d4", "r1bqkbnr/pppppppp/8/8/3NP3/8/PPPP1PPP/RNBQKB1R b KQkq - 0 3"),
+        ] {
+            ext_moves.push(board.make(board.parse_uci_move(uci_move).unwrap()));
+            assert_eq!(board.to_fen(), fen);
+        }
+
+        while let Some(ext_move) = ext_moves.pop() {
+            board.unmake(ext_move);
+        }
+        assert_eq!(board.to_fen(), Board::new().to_fen());
+    }
+
+    #[test]
+    fn test_fen_hash() {
+        assert_eq!(Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap().zobrist_hash, Board::new().zobrist_hash);
+
+        // The hash only depends on the position, not on how it was reached
+        let mut board = Board::new();
+        for uci_move in ["e2e4", "g8f6", "e4e5", "d7d5", "g1f3", "f6g8", "h1g1"] {
+            board.make(board.parse_uci_move(uci_move).unwrap());
+            assert_eq!(Board::from_fen(&board.to_fen()).unwrap().zobrist_hash, board.zobrist_hash, "{}", board.to_fen());
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,137 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameResult {
+    Ongoing,
+    // The color is the winner
+    Checkmate(Color),
+    Stalemate,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+}
+
+impl Board {
+    // True if the current position already happened before, it is enough to score it as a draw in the search
+    pub fn is_repetition(&self) -> bool {
+        self.repetition_count() > 0
+    }
+
+    // Number of previous occurrences of the current position
+    pub fn repetition_count(&self) -> usize {
+        // Positions before the last capture or pawn move cannot be repeated,
+        // and only positions with the same side to move can be equal
+        self.history.iter().rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|&&hash| hash == self.zobrist_hash)
+            .count()
+    }
+
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    // Positions where no sequence of legal moves can lead to a checkmate
+    pub fn is_insufficient_material(&self) -> bool {
+        if self.bitboards[PAWN] | self.bitboards[ROOK] | self.bitboards[QUEEN] != EMPTY {
+            return false;
+        }
+
+        let minor_pieces = self.bitboards[KNIGHT] | self.bitboards[BISHOP];
+        // KvK, KNvK and KBvK
+        if minor_pieces.count_ones() <= 1 {
+            return true;
+        }
+
+        // Any number of bishops all on the same square color
+        self.bitboards[KNIGHT] == EMPTY
+            && (self.bitboards[BISHOP] & LIGHT_SQUARES == EMPTY || self.bitboards[BISHOP] & DARK_SQUARES == EMPTY)
+    }
+
+    pub fn game_result(&self) -> GameResult {
+        // Checkmate takes precedence over the fifty-move rule
+        if self.legal_move_gen().is_empty() {
+            if self.in_check() {
+                GameResult::Checkmate(!self.to_move)
+            } else {
+                GameResult::Stalemate
+            }
+        } else if self.is_fifty_move_draw() {
+            GameResult::FiftyMoveRule
+        } else if self.repetition_count() >= 2 {
+            GameResult::ThreefoldRepetition
+        } else if self.is_insufficient_material() {
+            GameResult::InsufficientMaterial
+        } else {
+            GameResult::Ongoing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(board: &mut Board, moves: &str) {
+        for m in moves.split_ascii_whitespace() {
+            board.make(board.parse_uci_move(m).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_repetition() {
+        let mut board = Board::new();
+        assert!(!board.is_repetition());
+
+        play(&mut board, "g1f3 g8f6 f3g1 f6g8");
+        assert!(board.is_repetition());
+        assert_eq!(board.game_result(), GameResult::Ongoing);
+
+        play(&mut board, "g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(board.repetition_count(), 2);
+        assert_eq!(board.game_result(), GameResult::ThreefoldRepetition);
+
+        // A pawn move makes previous positions unreachable
+        play(&mut board, "e2e3 e7e6 g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(board.repetition_count(), 1);
+
+        let ext_move = board.make(board.parse_uci_move("g1f3").unwrap());
+        board.unmake(ext_move);
+        assert_eq!(board.repetition_count(), 1);
+    }
+
+    #[test]
+    fn test_fifty_move_rule() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        assert!(!board.is_fifty_move_draw());
+        play(&mut board, "a1a2");
+        assert!(board.is_fifty_move_draw());
+        assert_eq!(board.game_result(), GameResult::FiftyMoveRule);
+
+        // Checkmate on the hundredth ply still counts
+        let mut board = Board::from_fen("4k3/R7/8/8/8/8/8/1R2K3 w - - 99 80").unwrap();
+        play(&mut board, "b1b8");
+        assert_eq!(board.game_result(), GameResult::Checkmate(WHITE));
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4KN2 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(Board::from_fen("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(!Board::from_fen("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(!Board::from_fen("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(!Board::from_fen("4k3/8/8/8/8/8/P7/4K3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(!Board::new().is_insufficient_material());
+    }
+
+    #[test]
+    fn test_game_result() {
+        assert_eq!(Board::new().game_result(), GameResult::Ongoing);
+        assert_eq!(Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap().game_result(), GameResult::Stalemate);
+        assert_eq!(Board::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap().game_result(), GameResult::Checkmate(WHITE));
+        assert_eq!(Board::from_fen("7k/8/6K1/8/8/8/8/8 b - - 0 1").unwrap().game_result(), GameResult::InsufficientMaterial);
+    }
+}
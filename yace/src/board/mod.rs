@@ -1,4 +1,6 @@
-use crate::evaluation::IncrementalEval;
+use std::sync::Arc;
+
+use crate::{evaluation::{Evaluator, IncrementalEval}, nnue::{Network, Nnue}};
 
 pub use self::piece::*;
 pub use self::bitboard::*;
@@ -7,18 +9,23 @@ pub use self::moves::*;
 pub use self::square::*;
 
 use enum_indexed::*;
-use zobrist::ZobristHash;
+pub use zobrist::ZobristHash;
 use zobrist::ZobristHasher;
 
 pub mod bitboard;
 pub mod piece;
 pub mod fen;
+pub mod game_result;
+pub mod notation;
 pub mod square;
 pub mod magic_table;
 pub mod move_gen;
 pub mod moves;
+pub mod polyglot;
 
 mod zobrist;
+// Standalone search for magic factors, only needed to regenerate the tables
+#[allow(dead_code)]
 mod generate_magic;
 mod enum_indexed;
 
@@ -32,9 +39,19 @@ pub struct Board {
     castling_rights: CastlingRights,
     ep_target: Option<Square>,
     pub to_move: Color,
+    // Number of plies since the last capture or pawn move, for the fifty-move rule
+    halfmove_clock: u8,
+    // Starts at 1 and is incremented after each black move
+    fullmove_number: u16,
 
     pub evaluation: IncrementalEval,
+    // Only set when a network is loaded, it then replaces the incremental evaluation in the search
+    pub nnue: Option<Nnue>,
     pub zobrist_hash: ZobristHash,
+    // Only includes the pawns, used to cache the pawn structure evaluation
+    pub pawn_hash: ZobristHash,
+    // Hashes of all the positions before the current one, the last one being the previous position
+    history: Vec<ZobristHash>,
 }
 
 pub type CastlingSide = bool;
@@ -57,25 +74,54 @@ impl Board {
     }
 
     pub fn empty() -> Self {
-        Board {
+        let mut board = Board {
             pieces: ColorIndexed::new(),
             bitboards: PieceIndexed::new(),
             squares: [None; 64],
             ep_target: None,
             castling_rights: CastlingRights::new(),
             to_move: WHITE,
+            halfmove_clock: 0,
+            fullmove_number: 1,
 
             evaluation: IncrementalEval::new(),
+            nnue: None,
             zobrist_hash: ZobristHasher::new_hash(),
-        }
+            pawn_hash: ZobristHasher::new_hash(),
+            history: Vec::with_capacity(256),
+        };
+
+        // The hash always includes the current castling rights
+        board.zobrist_hash.handle_castling(board.castling_rights);
+        board
+    }
+
+    // The accumulator is computed from scratch, it is then updated incrementally
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = network.map(|network| Nnue::new(network, self));
     }
 
     // Move must be legal
     pub fn make(&mut self, to_play: Move) -> ExtendedMove {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push();
+        }
         let past_ep_state = self.ep_target;
         let past_castle = self.castling_rights;
+        let past_halfmove_clock = self.halfmove_clock;
+        self.history.push(self.zobrist_hash);
         self.zobrist_hash.handle_castling(past_castle);
 
+        // The clock is reset on captures and pawn moves
+        if self.squares[to_play.from() as usize] == Some(PAWN) || self.squares[to_play.to() as usize].is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock = self.halfmove_clock.saturating_add(1);
+        }
+        if self.to_move == BLACK {
+            self.fullmove_number += 1;
+        }
+
         // For en passant the captured piece is not set to pawn as we already know it
         let captured_piece = match to_play.infos() {
             MoveInfo::Capture | MoveInfo::CapturePromotion(_) => {
@@ -149,13 +195,22 @@ impl Board {
         self.to_move = !self.to_move;
         self.zobrist_hash.handle_side_to_move();
 
-        ExtendedMove::new_base(to_play, captured_piece, past_ep_state, past_castle)
+        ExtendedMove::new_base(to_play, captured_piece, past_ep_state, past_castle, past_halfmove_clock)
     }
 
     pub fn unmake(&mut self, ext_move: ExtendedMove) {
+        // The previous accumulator is on the stack, the pieces are restored without updating it
+        let nnue = self.nnue.take();
+
         self.to_move = !self.to_move;
         self.zobrist_hash.handle_side_to_move();
 
+        self.halfmove_clock = ext_move.infos().past_halfmove_clock();
+        if self.to_move == BLACK {
+            self.fullmove_number -= 1;
+        }
+        self.history.pop();
+
         // Remove and restore
         self.zobrist_hash.handle_ep(self.ep_target);
         self.ep_target = ext_move.infos().past_epstate();
@@ -204,6 +259,38 @@ impl Board {
             },
             _ => {}
         }
+
+        self.nnue = nnue;
+        if let Some(nnue) = &mut self.nnue {
+            nnue.pop();
+        }
+    }
+
+    // Passes the turn, the position must not be in check
+    // Repetitions are not detected across a null move as the halfmove clock is reset
+    pub fn make_null(&mut self) -> ExtendedMove {
+        let ext_move = ExtendedMove::new_base(Move::new(), None, self.ep_target, self.castling_rights, self.halfmove_clock);
+        self.history.push(self.zobrist_hash);
+        self.halfmove_clock = 0;
+
+        self.zobrist_hash.handle_ep(self.ep_target);
+        self.ep_target = None;
+
+        self.to_move = !self.to_move;
+        self.zobrist_hash.handle_side_to_move();
+
+        ext_move
+    }
+
+    pub fn unmake_null(&mut self, ext_move: ExtendedMove) {
+        self.to_move = !self.to_move;
+        self.zobrist_hash.handle_side_to_move();
+
+        self.ep_target = ext_move.infos().past_epstate();
+        self.zobrist_hash.handle_ep(self.ep_target);
+
+        self.halfmove_clock = ext_move.infos().past_halfmove_clock();
+        self.history.pop();
     }
 
     fn add_piece(&mut self, piece: Piece, sq: Square, color: Color) {
@@ -212,7 +299,13 @@ impl Board {
         self.pieces[color] |= sq.as_bitboard();
 
         self.evaluation.add_piece(piece, sq, color);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.add_piece(piece, sq, color);
+        }
         self.zobrist_hash.handle_piece(sq, piece, color);
+        if piece == PAWN {
+            self.pawn_hash.handle_piece(sq, piece, color);
+        }
     }
 
     fn remove_piece(&mut self, sq: Square, color: Color) -> Piece {
@@ -222,7 +315,13 @@ impl Board {
         self.pieces[color] &= !sq.as_bitboard();
 
         self.evaluation.remove_piece(piece, sq, color);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.remove_piece(piece, sq, color);
+        }
         self.zobrist_hash.handle_piece(sq, piece, color);
+        if piece == PAWN {
+            self.pawn_hash.handle_piece(sq, piece, color);
+        }
 
         piece
     }
@@ -239,8 +338,15 @@ impl Board {
         self.pieces[color] |= to.as_bitboard();
 
         self.evaluation.move_piece(piece, from, to, color);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.move_piece(piece, from, to, color);
+        }
         self.zobrist_hash.handle_piece(from, piece, color);
         self.zobrist_hash.handle_piece(to, piece, color);
+        if piece == PAWN {
+            self.pawn_hash.handle_piece(from, piece, color);
+            self.pawn_hash.handle_piece(to, piece, color);
+        }
     }
 
     fn king_square(&self, color: Color) -> Square {
@@ -251,10 +357,31 @@ impl Board {
         self.pieces[WHITE] | self.pieces[BLACK]
     }
 
+    pub fn castling_rights(&self) -> CastlingRights {
+        self.castling_rights
+    }
+
+    pub fn halfmove_clock(&self) -> u8 {
+        self.halfmove_clock
+    }
+
     fn checkers<const COLOR: bool>(&self) -> Bitboard {
         self.square_attacked_by::<COLOR>(self.king_square(COLOR))
     }
 
+    pub fn in_check(&self) -> bool {
+        if self.to_move == WHITE {
+            self.checkers::<WHITE>() != EMPTY
+        } else {
+            self.checkers::<BLACK>() != EMPTY
+        }
+    }
+
+    // Zugzwang is likely when only pawns are left
+    pub fn has_non_pawn_material(&self, color: Color) -> bool {
+        self.pieces[color] & !(self.bitboards[PAWN] | self.bitboards[KING]) != EMPTY
+    }
+
     pub fn display(&self) {
         for rank in RANK_LIST.into_iter().rev() {
             for file in FILE_LIST {
@@ -278,6 +405,7 @@ pub trait CastlingRightsExt {
     fn new() -> Self;
     fn index(color: Color, side: CastlingSide) -> usize;
     fn remove(&mut self, color: Color, side: CastlingSide);
+    #[allow(dead_code)]
     fn restore(&mut self, color: Color, side: CastlingSide);
     fn has(&self, color: Color, side: CastlingSide) -> bool;
 }
@@ -345,4 +473,34 @@ mod tests {
         assert!(!castling.has(BLACK, KINGSIDE));
         assert!(castling.has(BLACK, QUEENSIDE));
     }
+
+    #[test]
+    fn test_null_move() {
+        let mut board = Board::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/5N2/PPPP1PPP/RNBQKB1R b KQkq e3 0 3").unwrap();
+        let fen = board.to_fen();
+        let hash = board.zobrist_hash;
+
+        let ext_move = board.make_null();
+        assert_eq!(board.to_move, WHITE);
+        assert_eq!(board.to_fen(), "rnbqkbnr/ppp1pppp/8/8/3pP3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3");
+        assert_eq!(board.zobrist_hash, Board::from_fen(&board.to_fen()).unwrap().zobrist_hash);
+
+        board.unmake_null(ext_move);
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.zobrist_hash, hash);
+    }
+    #[test]
+    fn test_pawn_hash() {
+        let mut board = Board::new();
+        let pawn_hash = board.pawn_hash;
+        board.make(board.parse_uci_move("b1c3").unwrap());
+        assert_eq!(board.pawn_hash, pawn_hash);
+
+        // Captures, en passant and promotions all update the pawn hash incrementally
+        for uci_move in ["d7d5", "e2e4", "d5e4", "d2d4", "e4e3", "d4d5", "e7e5", "d5e6", "e3f2", "e1e2", "f2g1q"] {
+            board.make(board.parse_uci_move(uci_move).unwrap());
+            assert_eq!(board.pawn_hash, Board::from_fen(&board.to_fen()).unwrap().pawn_hash, "{}", board.to_fen());
+        }
+        assert_ne!(board.pawn_hash, pawn_hash);
+    }
 }
\ No newline at end of file
@@ -5,8 +5,8 @@ use bit_iter::BitIter;
 
 use super::{magic_table::bishop_attack, magic_table::rook_attack, *};
 
-static KNIGHT_ATTACK: LazyLock<[Bitboard; 64]> = LazyLock::new(initialize_knight_attack);
-static KING_ATTACK: LazyLock<[Bitboard; 64]> = LazyLock::new(initialize_king_attack);
+pub static KNIGHT_ATTACK: LazyLock<[Bitboard; 64]> = LazyLock::new(initialize_knight_attack);
+pub static KING_ATTACK: LazyLock<[Bitboard; 64]> = LazyLock::new(initialize_king_attack);
 static EP_FROM_SQUARES: LazyLock<[Bitboard; 16]> = LazyLock::new(initialize_ep_from_squares);
 static EMPTY_CASTLING_SQUARES: LazyLock<[Bitboard; 4]> = LazyLock::new(initialize_castle_empty_squares);
 static CHECK_CASTLE_SQUARES: LazyLock<[Bitboard; 4]> = LazyLock::new(initialize_castle_check_squares);
@@ -23,11 +23,158 @@ pub const MAX_MOVE_NUMBER: usize = 256;
 
 impl Board {
     pub fn legal_move_gen(&self) -> ArrayVec<Move, MAX_MOVE_NUMBER> {
+        match (self.to_move, self.in_check()) {
+            (WHITE, false) => MoveGenerator::new(self).generate::<WHITE, NON_EVASION>(),
+            (WHITE, true) => MoveGenerator::new(self).generate::<WHITE, EVASION>(),
+            (BLACK, false) => MoveGenerator::new(self).generate::<BLACK, NON_EVASION>(),
+            (BLACK, true) => MoveGenerator::new(self).generate::<BLACK, EVASION>(),
+        }
+    }
+
+    // Captures and queen promotions, or all the evasions when in check
+    pub fn legal_capture_gen(&self) -> ArrayVec<Move, MAX_MOVE_NUMBER> {
+        match (self.to_move, self.in_check()) {
+            (WHITE, false) => MoveGenerator::new(self).generate::<WHITE, CAPTURE>(),
+            (WHITE, true) => MoveGenerator::new(self).generate::<WHITE, EVASION>(),
+            (BLACK, false) => MoveGenerator::new(self).generate::<BLACK, CAPTURE>(),
+            (BLACK, true) => MoveGenerator::new(self).generate::<BLACK, EVASION>(),
+        }
+    }
+
+    // Every legal move not generated by legal_capture_gen, so nothing when in check
+    pub fn legal_quiet_gen(&self) -> ArrayVec<Move, MAX_MOVE_NUMBER> {
+        match (self.to_move, self.in_check()) {
+            (_, true) => ArrayVec::new(),
+            (WHITE, false) => MoveGenerator::new(self).generate::<WHITE, QUIET>(),
+            (BLACK, false) => MoveGenerator::new(self).generate::<BLACK, QUIET>(),
+        }
+    }
+
+    // Whether m is one of the moves legal_move_gen generates, without generating them
+    // Meant for moves coming from other positions, like the hash move or the killers
+    pub fn is_legal(&self, m: Move) -> bool {
         if self.to_move == WHITE {
-            MoveGenerator::new(self).generate::<WHITE>()
+            self.is_legal_for::<WHITE>(m)
+        } else {
+            self.is_legal_for::<BLACK>(m)
+        }
+    }
+
+    fn is_legal_for<const COLOR: bool>(&self, m: Move) -> bool {
+        let (from, to) = (m.from(), m.to());
+        let Some(piece) = self.squares[from as usize] else { return false };
+        if !self.pieces[COLOR].has(from) || self.pieces[COLOR].has(to) {
+            return false;
+        }
+        let capture = self.pieces[!COLOR].has(to);
+        let promotion_rank = if COLOR == WHITE {RANK8} else {RANK1};
+
+        let pseudo_legal = if piece == PAWN {
+            let push = !capture && from.forward::<COLOR>() == to;
+            let diagonal = capture && (from.forward_left::<COLOR>() == Some(to) || from.forward_right::<COLOR>() == Some(to));
+            match m.infos() {
+                MoveInfo::Quiet => push && !promotion_rank.has(to),
+                MoveInfo::DoublePawnPush => {
+                    let base_rank = if COLOR == WHITE {RANK2} else {RANK7};
+                    let middle = from.forward::<COLOR>();
+                    base_rank.has(from) && self.squares[middle as usize].is_none() && !capture && middle.forward::<COLOR>() == to
+                },
+                MoveInfo::Capture => diagonal && !promotion_rank.has(to),
+                MoveInfo::EnPassantCapture => self.ep_target.is_some_and(|ep_target| {
+                    ep_target.forward::<COLOR>() == to && !capture && EP_FROM_SQUARES[(ep_target - A4) as usize].has(from)
+                }),
+                MoveInfo::Promotion(_) => push && promotion_rank.has(to),
+                MoveInfo::CapturePromotion(_) => diagonal && promotion_rank.has(to),
+                MoveInfo::KingCastle | MoveInfo::QueenCastle => false,
+            }
         } else {
-            MoveGenerator::new(self).generate::<BLACK>()
+            let attack = if piece == KNIGHT {
+                KNIGHT_ATTACK[from as usize]
+            } else if piece == BISHOP {
+                self.bishop_attack(from)
+            } else if piece == ROOK {
+                self.rook_attack(from)
+            } else if piece == QUEEN {
+                self.bishop_attack(from) | self.rook_attack(from)
+            } else {
+                KING_ATTACK[from as usize]
+            };
+            match m.infos() {
+                MoveInfo::Quiet => !capture && attack.has(to),
+                MoveInfo::Capture => capture && attack.has(to),
+                MoveInfo::KingCastle => piece == KING && self.castle_is_open::<COLOR>(KINGSIDE, to),
+                MoveInfo::QueenCastle => piece == KING && self.castle_is_open::<COLOR>(QUEENSIDE, to),
+                _ => false,
+            }
+        };
+        if !pseudo_legal {
+            return false;
+        }
+
+        // In check, only the king moves away or a piece captures or blocks a single checker
+        let king_square = self.king_square(COLOR);
+        let checkers = self.checkers::<COLOR>();
+        if checkers != EMPTY {
+            if matches!(m.infos(), MoveInfo::KingCastle | MoveInfo::QueenCastle) {
+                return false;
+            }
+            if piece != KING {
+                let target = Bitboard::between(checkers.lsb(), king_square);
+                let captured_square = if m.infos() == MoveInfo::EnPassantCapture {self.ep_target.unwrap()} else {to};
+                if checkers.count_ones() > 1 || !(target.has(to) || target.has(captured_square)) {
+                    return false;
+                }
+            }
+        }
+
+        self.keeps_king_safe::<COLOR>(m, king_square, self.pinned_pieces())
+    }
+
+    fn castle_is_open<const COLOR: bool>(&self, side: CastlingSide, to: Square) -> bool {
+        let castle_index = CastlingRights::index(COLOR, side);
+        self.castling_rights.has(COLOR, side) && EMPTY_CASTLING_SQUARES[castle_index] & self.occupancy() == EMPTY
+            && KING_CASTLING_DEST[castle_index] == to
+    }
+
+    // Whether the king of the side to move is left out of check by a pseudo legal move, the pinned
+    // pieces being those of pinned_pieces. When in check, m must already capture or block the checker
+    fn keeps_king_safe<const COLOR: bool>(&self, m: Move, king_square: Square, pinned: Bitboard) -> bool {
+        if m.infos() == MoveInfo::EnPassantCapture {
+            let ep_target = self.ep_target.unwrap();
+            let occupancy = (self.pieces[WHITE] | self.pieces[BLACK]).unset(m.from()).unset(ep_target).set(m.to());
+            // if the attack is made by the en passant target, it doesn't count
+            if self.square_attacked_by_with_occ::<COLOR>(king_square, occupancy) != EMPTY 
+            && king_square.forward_left::<COLOR>() != self.ep_target && king_square.forward_right::<COLOR>() != self.ep_target {
+                return false
+            }
+        }
+
+        if pinned.has(m.from()) {
+            // if the piece is pinned, the king must be on the line of its movement
+            // if the movement is not a line then the bitboard is empty
+            return Bitboard::line(m.from(), m.to()).has(king_square)
+        }
+
+        if m.infos() == MoveInfo::KingCastle {
+            for sq in BitIter::from(CHECK_CASTLE_SQUARES[CastlingRights::index(COLOR, KINGSIDE)]) {
+                if self.square_attacked_by::<COLOR>(sq as Square) != EMPTY {
+                    return false
+                }
+            }
+        } else if m.infos() == MoveInfo::QueenCastle {
+            for sq in BitIter::from(CHECK_CASTLE_SQUARES[CastlingRights::index(COLOR, QUEENSIDE)]) {
+                if self.square_attacked_by::<COLOR>(sq as Square) != EMPTY {
+                    return false
+                }
+            }
         }
+
+        if let Some(KING) = self.squares[m.from() as usize] {
+            let occupancy = (self.pieces[WHITE] | self.pieces[BLACK]).unset(king_square);
+            return self.square_attacked_by_with_occ::<COLOR>(m.to(), occupancy) == EMPTY
+        }
+
+        true
     }
 
     fn bishop_attack(&self, sq: Square) -> Bitboard {
@@ -68,8 +215,13 @@ impl Board {
     }
 
     fn pinned_pieces(&self) -> Bitboard {
-        let king_square = self.king_square(self.to_move);
-        let enemy_pieces = self.pieces[!self.to_move];
+        self.pinned_pieces_of(self.to_move)
+    }
+
+    // Pieces standing alone between the king of this color and an enemy slider, of both colors
+    pub fn pinned_pieces_of(&self, color: Color) -> Bitboard {
+        let king_square = self.king_square(color);
+        let enemy_pieces = self.pieces[!color];
 
         let mut pinned = EMPTY;
 
@@ -126,53 +278,13 @@ impl<'a> MoveGenerator<'a> {
         }
     }
 
-    fn generate<const COLOR: bool>(mut self) -> ArrayVec<Move, MAX_MOVE_NUMBER> {
+    // EVASION must be used if and only if the king is in check
+    fn generate<const COLOR: bool, const KIND: u8>(mut self) -> ArrayVec<Move, MAX_MOVE_NUMBER> {
         let king_square = self.board.king_square(self.board.to_move);
-        if self.board.square_attacked_by::<COLOR>(king_square) != 0 {
-            self.pseudo_legal_movegen::<COLOR, EVASION>();
-        } else {
-            self.pseudo_legal_movegen::<COLOR, NON_EVASION>();
-        }
+        self.pseudo_legal_movegen::<COLOR, KIND>();
 
         let pinned = self.board.pinned_pieces();
-        self.moves.retain(|m| {
-            if m.infos() == MoveInfo::EnPassantCapture {
-                let ep_target = self.board.ep_target.unwrap();
-                let occupancy = (self.board.pieces[WHITE] | self.board.pieces[BLACK]).unset(m.from()).unset(ep_target).set(m.to());
-                // if the attack is made by the en passant target, it doesn't count
-                if self.board.square_attacked_by_with_occ::<COLOR>(king_square, occupancy) != EMPTY 
-                && king_square.forward_left::<COLOR>() != self.board.ep_target && king_square.forward_right::<COLOR>() != self.board.ep_target {
-                    return false
-                }
-            }
-
-            if pinned.has(m.from()) {
-                // if the piece is pinned, the king must be on the line of its movement
-                // if the movement is not a line then the bitboard is empty
-                return Bitboard::line(m.from(), m.to()).has(king_square)
-            }
-
-            if m.infos() == MoveInfo::KingCastle {
-                for sq in BitIter::from(CHECK_CASTLE_SQUARES[CastlingRights::index(COLOR, KINGSIDE)]) {
-                    if self.board.square_attacked_by::<COLOR>(sq as Square) != EMPTY {
-                        return false
-                    }
-                }
-            } else if m.infos() == MoveInfo::QueenCastle {
-                for sq in BitIter::from(CHECK_CASTLE_SQUARES[CastlingRights::index(COLOR, QUEENSIDE)]) {
-                    if self.board.square_attacked_by::<COLOR>(sq as Square) != EMPTY {
-                        return false
-                    }
-                }
-            }
-
-            if let Some(KING) = self.board.squares[m.from() as usize] {
-                let occupancy = (self.board.pieces[WHITE] | self.board.pieces[BLACK]).unset(king_square);
-                return self.board.square_attacked_by_with_occ::<COLOR>(m.to(), occupancy) == EMPTY
-            }
-
-            true
-        });
+        self.moves.retain(|&mut m| self.board.keeps_king_safe::<COLOR>(m, king_square, pinned));
 
         self.moves
     }
@@ -259,37 +371,36 @@ impl<'a> MoveGenerator<'a> {
 
         let promotion_rank = if COLOR == WHITE {RANK8} else {RANK1};
         
-        // Captures
-        if KIND != QUIET {
-            // Simple capture + promotion capture
-            for dest_square in BitIter::from(pawns.forward_left::<COLOR>() & self.board.pieces[!COLOR] & target) {
-                let dest_square = dest_square as Square;
-                let from_square = dest_square.backward_left::<COLOR>().unwrap();
-                if promotion_rank.has(dest_square) {
-                    self.make_promotion::<KIND, true>(from_square, dest_square);
-                } else {
-                    self.moves.push(Move::new_base(from_square, dest_square as Square).with_infos(MoveInfo::Capture));
-                }
+        // Captures, only the under promotions are generated with quiet moves
+        let capture_target = if KIND == QUIET {self.board.pieces[!COLOR] & promotion_rank} else {self.board.pieces[!COLOR] & target};
+        // Simple capture + promotion capture
+        for dest_square in BitIter::from(pawns.forward_left::<COLOR>() & capture_target) {
+            let dest_square = dest_square as Square;
+            let from_square = dest_square.backward_left::<COLOR>().unwrap();
+            if promotion_rank.has(dest_square) {
+                self.make_promotion::<KIND, true>(from_square, dest_square);
+            } else {
+                self.moves.push(Move::new_base(from_square, dest_square as Square).with_infos(MoveInfo::Capture));
             }
-            
-            for dest_square in BitIter::from(pawns.forward_right::<COLOR>() & self.board.pieces[!COLOR] & target) {
-                let dest_square = dest_square as Square;
-                let from_square = dest_square.backward_right::<COLOR>().unwrap();
-                if promotion_rank.has(dest_square) {
-                    self.make_promotion::<KIND, true>(from_square, dest_square);
-                } else {
-                    self.moves.push(Move::new_base(from_square, dest_square as Square).with_infos(MoveInfo::Capture));
-                }
+        }
+        
+        for dest_square in BitIter::from(pawns.forward_right::<COLOR>() & capture_target) {
+            let dest_square = dest_square as Square;
+            let from_square = dest_square.backward_right::<COLOR>().unwrap();
+            if promotion_rank.has(dest_square) {
+                self.make_promotion::<KIND, true>(from_square, dest_square);
+            } else {
+                self.moves.push(Move::new_base(from_square, dest_square as Square).with_infos(MoveInfo::Capture));
             }
-            
-            // En passant
-            if let Some(ep_target) = self.board.ep_target {
-                let ep_dest = ep_target.forward::<COLOR>();
-                if self.board.squares[ep_dest as usize].is_none() && (target.has(ep_dest) | target.has(ep_target)) {
-                    let capturing_pawns = EP_FROM_SQUARES[(ep_target - A4) as usize] & self.board.bitboards[PAWN] & self.board.pieces[COLOR];
-                    for from_square in BitIter::from(capturing_pawns) {
-                        self.moves.push(Move::new_base(from_square as Square, ep_dest).with_infos(MoveInfo::EnPassantCapture));
-                    }
+        }
+        
+        // En passant
+        if KIND != QUIET && let Some(ep_target) = self.board.ep_target {
+            let ep_dest = ep_target.forward::<COLOR>();
+            if self.board.squares[ep_dest as usize].is_none() && (target.has(ep_dest) | target.has(ep_target)) {
+                let capturing_pawns = EP_FROM_SQUARES[(ep_target - A4) as usize] & self.board.bitboards[PAWN] & self.board.pieces[COLOR];
+                for from_square in BitIter::from(capturing_pawns) {
+                    self.moves.push(Move::new_base(from_square as Square, ep_dest).with_infos(MoveInfo::EnPassantCapture));
                 }
             }
         }
@@ -299,18 +410,21 @@ impl<'a> MoveGenerator<'a> {
         let push_dest = pawns.forward::<COLOR>() & empty;
         let double_push_dest = (push_dest & base_rank).forward::<COLOR>() & empty & target;
 
-        if KIND != CAPTURE {
-            // simple
-            for dest_square in BitIter::from(push_dest & target) {
-                let dest_square = dest_square as Square;
-                let from_square = dest_square.backward::<COLOR>();
-                if promotion_rank.has(dest_square) {
-                    self.make_promotion::<KIND, false>(from_square, dest_square);
-                } else {
-                    self.moves.push(Move::new_base(from_square, dest_square));
-                }
+        // Queen promotions are generated with captures
+        let push_target = if KIND == CAPTURE {promotion_rank} else {target};
+
+        // simple
+        for dest_square in BitIter::from(push_dest & push_target) {
+            let dest_square = dest_square as Square;
+            let from_square = dest_square.backward::<COLOR>();
+            if promotion_rank.has(dest_square) {
+                self.make_promotion::<KIND, false>(from_square, dest_square);
+            } else {
+                self.moves.push(Move::new_base(from_square, dest_square));
             }
-    
+        }
+
+        if KIND != CAPTURE {
             // double
             for dest_square in BitIter::from(double_push_dest) {
                 let dest_square = dest_square as Square;
@@ -574,4 +688,71 @@ mod tests {
         assert_eq!(board.perft::<true>(4), 3_894_594);
         assert_eq!(board.perft::<true>(5), 164_075_551);
     }
+
+    // Captures and quiet moves are a partition of the legal moves
+    fn assert_capture_quiet_split(board: &mut Board, depth: u8) {
+        let mut split: Vec<u16> = board.legal_capture_gen().into_iter().chain(board.legal_quiet_gen()).map(Move::into_bits).collect();
+        let mut legal: Vec<u16> = board.legal_move_gen().into_iter().map(Move::into_bits).collect();
+        split.sort();
+        legal.sort();
+        assert_eq!(split, legal, "{}", board.to_fen());
+
+        if depth > 1 {
+            for m in board.legal_move_gen() {
+                let ext_move = board.make(m);
+                assert_capture_quiet_split(board, depth - 1);
+                board.unmake(ext_move);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capture_quiet_split() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            assert_capture_quiet_split(&mut Board::from_fen(fen).unwrap(), 3);
+        }
+    }
+
+    fn collect_nodes(board: &mut Board, depth: u8, nodes: &mut Vec<String>, pool: &mut Vec<Move>) {
+        nodes.push(board.to_fen());
+        for m in board.legal_move_gen() {
+            if !pool.contains(&m) {
+                pool.push(m);
+            }
+            if depth > 1 {
+                let ext_move = board.make(m);
+                collect_nodes(board, depth - 1, nodes, pool);
+                board.unmake(ext_move);
+            }
+        }
+    }
+
+    // Moves legal somewhere in the tree are checked against the generated moves of every node
+    #[test]
+    fn test_is_legal() {
+        let mut nodes = Vec::new();
+        let mut pool = Vec::new();
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            // The en passant capture removes the checking pawn
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+        ] {
+            collect_nodes(&mut Board::from_fen(fen).unwrap(), 3, &mut nodes, &mut pool);
+        }
+
+        for fen in nodes {
+            let board = Board::from_fen(&fen).unwrap();
+            let legal = board.legal_move_gen();
+            for &m in &pool {
+                assert_eq!(board.is_legal(m), legal.contains(&m), "{} {}", fen, m.to_uci());
+            }
+        }
+    }
 }
\ No newline at end of file
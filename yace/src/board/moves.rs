@@ -5,6 +5,7 @@ use bitfield_struct::bitfield;
 use super::*;
 
 #[bitfield(u16, debug=false)]
+#[derive(PartialEq, Eq)]
 pub struct Move {
     #[bits(6, from = std::convert::identity, into = std::convert::identity)]
     pub from: i8,
@@ -14,18 +15,34 @@ pub struct Move {
     pub infos: MoveInfo,
 }
 
-#[bitfield(u32)]
+#[bitfield(u64)]
 pub struct ExtendedMove {
     #[bits(16)]
     pub base_move: Move,
-    #[bits(16)]
+    #[bits(32)]
     pub infos: ExtMoveInfo,
+    #[bits(16)]
+    __: u16,
 }
 
 impl Move {
     pub fn new_base(from: Square, to: Square) -> Self {
         Self::new().with_from(from).with_to(to)
     }
+
+    // Long algebraic notation as used by UCI, e.g. "e2e4" or "e7e8q"
+    pub fn to_uci(self) -> String {
+        let mut output = self.from().name() + &self.to().name();
+        if let MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) = self.infos() {
+            output.push(char::from(piece));
+        }
+        output
+    }
+
+    // Neither a capture nor a promotion
+    pub fn is_quiet(self) -> bool {
+        matches!(self.infos(), MoveInfo::Quiet | MoveInfo::DoublePawnPush | MoveInfo::KingCastle | MoveInfo::QueenCastle)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -40,7 +57,7 @@ pub enum MoveInfo {
     CapturePromotion(Piece),
 }
 
-#[bitfield(u16, debug=false)]
+#[bitfield(u32, debug=false)]
 #[derive(Debug, PartialEq)]
 pub struct ExtMoveInfo {
     #[bits(3, from = captured_from_bits, into = captured_into_bits)]
@@ -49,8 +66,10 @@ pub struct ExtMoveInfo {
     pub past_epstate: Option<Square>,
     #[bits(4, from = std::convert::identity, into = std::convert::identity)]
     pub past_castle: CastlingRights,
-    #[bits(4)]
-    __: u8,
+    #[bits(8)]
+    pub past_halfmove_clock: u8,
+    #[bits(12)]
+    __: u16,
 }
 
 #[bitfield(u8)]
@@ -106,9 +125,9 @@ impl MoveInfo {
 }
 
 impl ExtendedMove {
-    pub fn new_base(base_move: Move, captured_piece: Option<Piece>, ep_state: Option<Square>, castle: CastlingRights) -> Self {
+    pub fn new_base(base_move: Move, captured_piece: Option<Piece>, ep_state: Option<Square>, castle: CastlingRights, halfmove_clock: u8) -> Self {
         Self::new().with_base_move(base_move)
-            .with_infos(ExtMoveInfo::new().with_captured_piece(captured_piece).with_past_epstate(ep_state).with_past_castle(castle))
+            .with_infos(ExtMoveInfo::new().with_captured_piece(captured_piece).with_past_epstate(ep_state).with_past_castle(castle).with_past_halfmove_clock(halfmove_clock))
     }
 }
 
@@ -170,6 +189,14 @@ mod tests {
         assert_eq!(Move::new().with_infos(MoveInfo::CapturePromotion(QUEEN)).infos(), MoveInfo::CapturePromotion(QUEEN));
     }
 
+    #[test]
+    fn test_is_quiet() {
+        assert!(Move::new().with_infos(MoveInfo::Quiet).is_quiet());
+        assert!(Move::new().with_infos(MoveInfo::KingCastle).is_quiet());
+        assert!(!Move::new().with_infos(MoveInfo::EnPassantCapture).is_quiet());
+        assert!(!Move::new().with_infos(MoveInfo::Promotion(KNIGHT)).is_quiet());
+    }
+
     #[test]
     fn test_from_to() {
         let m = Move::new().with_from(A3).with_to(D7);
@@ -177,6 +204,14 @@ mod tests {
         assert_eq!(m.to(), D7);
     }
 
+    #[test]
+    fn test_to_uci() {
+        assert_eq!(Move::new_base(E2, E4).with_infos(MoveInfo::DoublePawnPush).to_uci(), "e2e4");
+        assert_eq!(Move::new_base(E1, G1).with_infos(MoveInfo::KingCastle).to_uci(), "e1g1");
+        assert_eq!(Move::new_base(B7, A8).with_infos(MoveInfo::CapturePromotion(KNIGHT)).to_uci(), "b7a8n");
+        assert_eq!(Move::new_base(H2, H1).with_infos(MoveInfo::Promotion(QUEEN)).to_uci(), "h2h1q");
+    }
+
     #[test]
     fn test_ext_move() {
         let mut rights = CastlingRights::new();
@@ -194,5 +229,12 @@ mod tests {
         rights.restore(BLACK, KINGSIDE);
         let ext = ExtendedMove::new().with_infos(ExtMoveInfo::new().with_past_castle(rights));
         assert_eq!(ext.infos().past_castle(), rights);
+
+        let ext = ExtendedMove::new_base(Move::new_base(E2, E4), Some(ROOK), Some(D5), rights, 99);
+        assert_eq!(ext.base_move(), Move::new_base(E2, E4));
+        assert_eq!(ext.infos().captured_piece(), Some(ROOK));
+        assert_eq!(ext.infos().past_epstate(), Some(D5));
+        assert_eq!(ext.infos().past_castle(), rights);
+        assert_eq!(ext.infos().past_halfmove_clock(), 99);
     }
 }
\ No newline at end of file
@@ -0,0 +1,278 @@
+use std::fmt::Display;
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveParseError {
+    // The string is not a well formed move
+    InvalidSyntax(String),
+    // The string is well formed but doesn't match any legal move in the position
+    IllegalMove(String),
+    // The string matches several legal moves, only possible with SAN
+    AmbiguousMove(String),
+}
+
+impl Display for MoveParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveParseError::InvalidSyntax(m) => write!(f, "invalid move syntax: {m}"),
+            MoveParseError::IllegalMove(m) => write!(f, "illegal move: {m}"),
+            MoveParseError::AmbiguousMove(m) => write!(f, "ambiguous move: {m}"),
+        }
+    }
+}
+
+impl Board {
+    // The move is matched against legal moves so that the right MoveInfo is set
+    pub fn parse_uci_move(&self, move_str: &str) -> Result<Move, MoveParseError> {
+        let syntax_error = || MoveParseError::InvalidSyntax(move_str.to_string());
+
+        if !move_str.is_ascii() || !(4..=5).contains(&move_str.len()) {
+            return Err(syntax_error());
+        }
+        let from = square_from_str(&move_str[0..2]).ok_or_else(syntax_error)?;
+        let to = square_from_str(&move_str[2..4]).ok_or_else(syntax_error)?;
+        let promotion = match move_str[4..].chars().next() {
+            None => None,
+            Some('n') => Some(KNIGHT),
+            Some('b') => Some(BISHOP),
+            Some('r') => Some(ROOK),
+            Some('q') => Some(QUEEN),
+            Some(_) => return Err(syntax_error()),
+        };
+
+        self.legal_move_gen().into_iter()
+            .find(|m| m.from() == from && m.to() == to && match m.infos() {
+                MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) => Some(piece) == promotion,
+                _ => promotion.is_none(),
+            })
+            .ok_or_else(|| MoveParseError::IllegalMove(move_str.to_string()))
+    }
+
+    // Standard Algebraic Notation, e.g. "Nbd7", "exd6", "O-O-O", "e8=Q+" or "Qxf7#"
+    // The move must be legal
+    pub fn to_san(&self, m: Move) -> String {
+        let mut output = match m.infos() {
+            MoveInfo::KingCastle => String::from("O-O"),
+            MoveInfo::QueenCastle => String::from("O-O-O"),
+            infos => {
+                let piece = self.squares[m.from() as usize].unwrap();
+                let capture = matches!(infos, MoveInfo::Capture | MoveInfo::CapturePromotion(_) | MoveInfo::EnPassantCapture);
+                let mut output = String::with_capacity(8);
+
+                if piece == PAWN {
+                    if capture {
+                        output.push(m.from().name().remove(0));
+                    }
+                } else {
+                    output.push(san_piece_char(piece));
+
+                    // Other pieces of the same kind that can reach the same square
+                    let ambiguous: Vec<Move> = self.legal_move_gen().into_iter()
+                        .filter(|other| other.to() == m.to() && other.from() != m.from() && self.squares[other.from() as usize] == Some(piece))
+                        .collect();
+                    if !ambiguous.is_empty() {
+                        let from_name = m.from().name();
+                        if ambiguous.iter().all(|other| other.from().file() != m.from().file()) {
+                            output.push_str(&from_name[0..1]);
+                        } else if ambiguous.iter().all(|other| other.from().rank() != m.from().rank()) {
+                            output.push_str(&from_name[1..2]);
+                        } else {
+                            output.push_str(&from_name);
+                        }
+                    }
+                }
+
+                if capture {
+                    output.push('x');
+                }
+                output.push_str(&m.to().name());
+
+                if let MoveInfo::Promotion(prom_piece) | MoveInfo::CapturePromotion(prom_piece) = infos {
+                    output.push('=');
+                    output.push(san_piece_char(prom_piece));
+                }
+                output
+            }
+        };
+
+        let mut next_board = self.clone();
+        next_board.make(m);
+        if next_board.in_check() {
+            output.push(if next_board.legal_move_gen().is_empty() {'#'} else {'+'});
+        }
+
+        output
+    }
+
+    // Accepts check and annotation suffixes, "0-0" castling and an optional "e.p." after en passant captures
+    pub fn parse_san(&self, move_str: &str) -> Result<Move, MoveParseError> {
+        let syntax_error = || MoveParseError::InvalidSyntax(move_str.to_string());
+
+        let san = move_str.trim();
+        let san = san.strip_suffix("e.p.").unwrap_or(san).trim_end();
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        if !san.is_ascii() || san.is_empty() {
+            return Err(syntax_error());
+        }
+
+        let candidates: Vec<Move> = if matches!(san, "O-O" | "0-0") {
+            self.legal_move_gen().into_iter().filter(|m| m.infos() == MoveInfo::KingCastle).collect()
+        } else if matches!(san, "O-O-O" | "0-0-0") {
+            self.legal_move_gen().into_iter().filter(|m| m.infos() == MoveInfo::QueenCastle).collect()
+        } else {
+            let (piece, rest) = match san.chars().next() {
+                Some(c) if c.is_ascii_uppercase() => (san_piece_from_char(c).ok_or_else(syntax_error)?, &san[1..]),
+                _ => (PAWN, san),
+            };
+
+            let (rest, promotion) = match rest.rsplit_once('=') {
+                Some((rest, prom)) => {
+                    let mut chars = prom.chars();
+                    match (chars.next().and_then(san_piece_from_char), chars.next()) {
+                        (Some(prom_piece), None) if prom_piece != PAWN && prom_piece != KING => (rest, Some(prom_piece)),
+                        _ => return Err(syntax_error()),
+                    }
+                },
+                // Some tools omit the '=' sign
+                None if piece == PAWN && rest.ends_with(['N', 'B', 'R', 'Q']) => {
+                    (&rest[..rest.len()-1], rest.chars().last().and_then(san_piece_from_char))
+                },
+                None => (rest, None),
+            };
+
+            if rest.len() < 2 {
+                return Err(syntax_error());
+            }
+            let to = square_from_str(&rest[rest.len()-2..]).ok_or_else(syntax_error)?;
+            let disambiguation = rest[..rest.len()-2].trim_end_matches('x');
+
+            let mut from_file = None;
+            let mut from_rank = None;
+            for c in disambiguation.chars() {
+                match c {
+                    'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some((c as u8 - b'a') as i8),
+                    '1'..='8' if from_rank.is_none() => from_rank = Some((c as u8 - b'1') as i8),
+                    _ => return Err(syntax_error()),
+                }
+            }
+
+            self.legal_move_gen().into_iter()
+                .filter(|m| {
+                    m.to() == to
+                    && self.squares[m.from() as usize] == Some(piece)
+                    && !matches!(m.infos(), MoveInfo::KingCastle | MoveInfo::QueenCastle)
+                    && from_file.is_none_or(|file| m.from().file() == file)
+                    && from_rank.is_none_or(|rank| m.from().rank() == rank)
+                    && match m.infos() {
+                        MoveInfo::Promotion(prom_piece) | MoveInfo::CapturePromotion(prom_piece) => Some(prom_piece) == promotion,
+                        _ => promotion.is_none(),
+                    }
+                })
+                .collect()
+        };
+
+        match candidates.as_slice() {
+            [m] => Ok(*m),
+            [] => Err(MoveParseError::IllegalMove(move_str.to_string())),
+            _ => Err(MoveParseError::AmbiguousMove(move_str.to_string())),
+        }
+    }
+}
+
+fn san_piece_char(piece: Piece) -> char {
+    char::from(piece).to_ascii_uppercase()
+}
+
+fn san_piece_from_char(c: char) -> Option<Piece> {
+    match c {
+        'N' => Some(KNIGHT),
+        'B' => Some(BISHOP),
+        'R' => Some(ROOK),
+        'Q' => Some(QUEEN),
+        'K' => Some(KING),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uci_move() {
+        let board = Board::new();
+        assert_eq!(board.parse_uci_move("e2e4").unwrap().infos(), MoveInfo::DoublePawnPush);
+        assert_eq!(board.parse_uci_move("g1f3").unwrap().infos(), MoveInfo::Quiet);
+        assert_eq!(board.parse_uci_move("e2e5"), Err(MoveParseError::IllegalMove("e2e5".to_string())));
+        assert_eq!(board.parse_uci_move("e2e9"), Err(MoveParseError::InvalidSyntax("e2e9".to_string())));
+        assert_eq!(board.parse_uci_move("e2e4k"), Err(MoveParseError::InvalidSyntax("e2e4k".to_string())));
+
+        let board = Board::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        assert_eq!(board.parse_uci_move("e1g1").unwrap().infos(), MoveInfo::KingCastle);
+        assert_eq!(board.parse_uci_move("e1c1").unwrap().infos(), MoveInfo::QueenCastle);
+        assert_eq!(board.parse_uci_move("a1a8").unwrap().infos(), MoveInfo::Capture);
+        assert_eq!(board.parse_uci_move("e5d6").unwrap().infos(), MoveInfo::EnPassantCapture);
+        assert_eq!(board.parse_uci_move("b7b8r").unwrap().infos(), MoveInfo::Promotion(ROOK));
+        assert_eq!(board.parse_uci_move("b7a8q").unwrap().infos(), MoveInfo::CapturePromotion(QUEEN));
+        assert!(board.parse_uci_move("b7b8").is_err());
+
+        for m in board.legal_move_gen() {
+            assert_eq!(board.parse_uci_move(&m.to_uci()), Ok(m));
+        }
+    }
+
+    #[test]
+    fn test_to_san() {
+        let board = Board::new();
+        assert_eq!(board.to_san(board.parse_uci_move("e2e4").unwrap()), "e4");
+        assert_eq!(board.to_san(board.parse_uci_move("g1f3").unwrap()), "Nf3");
+
+        // Disambiguation by file, by rank and by both
+        let board = Board::from_fen("r3k2r/8/8/8/1N3N2/8/1N6/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(board.to_san(board.parse_uci_move("f4d3").unwrap()), "Nfd3");
+        assert_eq!(board.to_san(board.parse_uci_move("b2d3").unwrap()), "N2d3");
+        assert_eq!(board.to_san(board.parse_uci_move("b4d3").unwrap()), "Nb4d3");
+        assert_eq!(board.to_san(board.parse_uci_move("e1c1").unwrap()), "O-O-O");
+        assert_eq!(board.to_san(board.parse_uci_move("a1a8").unwrap()), "Rxa8+");
+
+        let board = Board::from_fen("6k1/3P4/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.to_san(board.parse_uci_move("d7d8q").unwrap()), "d8=Q+");
+
+        // Scholar's mate
+        let board = Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+        assert_eq!(board.to_san(board.parse_uci_move("h5f7").unwrap()), "Qxf7#");
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let board = Board::from_fen("r3k2r/8/8/8/1N3N2/8/1N6/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(board.parse_san("Nfd3").unwrap().from(), F4);
+        assert_eq!(board.parse_san("N2d3").unwrap().from(), B2);
+        assert_eq!(board.parse_san("Nb4d3").unwrap().from(), B4);
+        assert_eq!(board.parse_san("O-O").unwrap().infos(), MoveInfo::KingCastle);
+        assert_eq!(board.parse_san("0-0-0").unwrap().infos(), MoveInfo::QueenCastle);
+        assert_eq!(board.parse_san("Rxa8+").unwrap().infos(), MoveInfo::Capture);
+        assert_eq!(board.parse_san("Nd3"), Err(MoveParseError::AmbiguousMove("Nd3".to_string())));
+        assert_eq!(board.parse_san("Nbd3"), Err(MoveParseError::AmbiguousMove("Nbd3".to_string())));
+        assert_eq!(board.parse_san("Qd3"), Err(MoveParseError::IllegalMove("Qd3".to_string())));
+        assert_eq!(board.parse_san("Nd9"), Err(MoveParseError::InvalidSyntax("Nd9".to_string())));
+
+        let board = Board::from_fen("6k1/3P4/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.parse_san("d8=Q+").unwrap().infos(), MoveInfo::Promotion(QUEEN));
+        assert_eq!(board.parse_san("d8N").unwrap().infos(), MoveInfo::Promotion(KNIGHT));
+        assert!(board.parse_san("d8").is_err());
+
+        let mut board = Board::new();
+        for san in ["e4", "Nf6", "e5", "d5"] {
+            let m = board.parse_san(san).unwrap();
+            board.make(m);
+        }
+        assert_eq!(board.parse_san("exd6 e.p.").unwrap().infos(), MoveInfo::EnPassantCapture);
+        assert_eq!(board.to_san(board.parse_san("exd6").unwrap()), "exd6");
+
+        for m in board.legal_move_gen() {
+            assert_eq!(board.parse_san(&board.to_san(m)), Ok(m));
+        }
+    }
+}
@@ -1,3 +1,6 @@
+// Not every square constant is used by the engine
+#![allow(dead_code)]
+
 use super::*;
 
 pub const A: i8 = 0;
@@ -83,6 +86,14 @@ pub const fn square_from_name(file: i8, rank: i8) -> Square {
     8*(rank-1) + file
 }
 
+// Parses a lowercase square name like "e4"
+pub fn square_from_str(name: &str) -> Option<Square> {
+    match name.as_bytes() {
+        &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some(square_from_name((file - b'a') as i8, (rank - b'0') as i8)),
+        _ => None,
+    }
+}
+
 pub trait SquareExt {
     fn new(file: i8, rank: i8) -> Self;
     fn file(self) -> i8;
@@ -94,8 +105,10 @@ pub trait SquareExt {
     fn backward_left<const COLOR: bool>(self) -> Option<Square>;
     fn backward_right<const COLOR: bool>(self) -> Option<Square>;
     fn vertical_symmetry(self) -> Square;
+    #[allow(clippy::wrong_self_convention)]
     fn as_bitboard(self) -> Bitboard;
     fn debug(self) -> String;
+    fn name(self) -> String;
 }
 
 impl SquareExt for Square {
@@ -132,6 +145,11 @@ impl SquareExt for Square {
 
         output
     }
+
+    // Lowercase name used by UCI, SAN and FEN
+    fn name(self) -> String {
+        self.debug().to_lowercase()
+    }
     
     fn backward<const COLOR: bool>(self) -> Square {
         if COLOR == WHITE {
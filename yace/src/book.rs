@@ -0,0 +1,393 @@
+// Polyglot opening books
+//
+// A book is a sequence of 16 bytes big endian entries sorted by key:
+// - key: u64, the Polyglot key of the position
+// - move: u16, bits 0-5 are the destination square, bits 6-11 the origin square and bits 12-14 the
+//   promotion piece (none, knight, bishop, rook, queen), castling is encoded as the king taking its rook
+// - weight: u16, how good the move is relative to the other moves of the position
+// - learn: u32, unused
+//
+// Books can be built from PGN files, a move being weighted by 2 per win and 1 per draw of the side playing it
+
+use std::{collections::HashMap, fmt::{self, Display}, fs, io, path::Path};
+
+use rand::Rng;
+
+use crate::board::*;
+
+pub const ENTRY_SIZE: usize = 16;
+
+// Only the first plies of the games are added when building a book
+pub const DEFAULT_BOOK_PLIES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookError {
+    Io(String),
+    InvalidSize(usize),
+}
+
+impl Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::Io(err) => write!(f, "can't read book file: {err}"),
+            BookError::InvalidSize(size) => write!(f, "book file size {size} is not a multiple of {ENTRY_SIZE}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookSelection {
+    // The move with the highest weight
+    Best,
+    // A random move with a probability proportional to its weight
+    Weighted,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OpeningBook {
+    entries: Vec<BookEntry>,
+}
+
+impl OpeningBook {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BookError> {
+        let bytes = fs::read(path).map_err(|err| BookError::Io(err.to_string()))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::InvalidSize(bytes.len()));
+        }
+
+        let entries = bytes.chunks_exact(ENTRY_SIZE).map(|entry| BookEntry {
+            key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+            raw_move: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(entry[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(entry[12..16].try_into().unwrap()),
+        }).collect();
+        Ok(Self::from_entries(entries))
+    }
+
+    // Books should already be sorted, this makes sure the binary search works on any file
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Self {
+        entries.sort_by_key(|entry| entry.key);
+        OpeningBook { entries }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(|entry| {
+            let mut bytes = [0; ENTRY_SIZE];
+            bytes[0..8].copy_from_slice(&entry.key.to_be_bytes());
+            bytes[8..10].copy_from_slice(&entry.raw_move.to_be_bytes());
+            bytes[10..12].copy_from_slice(&entry.weight.to_be_bytes());
+            bytes[12..16].copy_from_slice(&entry.learn.to_be_bytes());
+            bytes
+        }).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Entries of the position with this key, found by binary search
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = start + self.entries[start..].partition_point(|entry| entry.key == key);
+        &self.entries[start..end]
+    }
+
+    // Legal book moves of the position with their weight, entries that don't decode to a legal move are skipped
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        self.entries(board.polyglot_key()).iter()
+            .filter_map(|entry| decode_move(board, entry.raw_move).map(|m| (m, entry.weight)))
+            .collect()
+    }
+
+    // Moves with a zero weight are never played
+    pub fn probe(&self, board: &Board, selection: BookSelection, rng: &mut impl Rng) -> Option<Move> {
+        let moves: Vec<(Move, u16)> = self.moves(board).into_iter().filter(|&(_, weight)| weight > 0).collect();
+        match selection {
+            // The first of the best moves is kept on ties
+            BookSelection::Best => moves.iter().rev().max_by_key(|&&(_, weight)| weight).map(|&(m, _)| m),
+            BookSelection::Weighted => {
+                let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut choice = rng.random_range(0..total);
+                moves.into_iter().find(|&(_, weight)| {
+                    let found = choice < weight as u32;
+                    choice = choice.saturating_sub(weight as u32);
+                    found
+                }).map(|(m, _)| m)
+            },
+        }
+    }
+}
+
+// The promotion piece of the Polyglot encoding, 0 being no promotion
+fn promotion_code(piece: Piece) -> u16 {
+    match piece {
+        KNIGHT => 1,
+        BISHOP => 2,
+        ROOK => 3,
+        _ => 4,
+    }
+}
+
+// Castling is matched from the king taking its own rook
+pub fn decode_move(board: &Board, raw_move: u16) -> Option<Move> {
+    let to = (raw_move & 0x3f) as Square;
+    let from = ((raw_move >> 6) & 0x3f) as Square;
+    let promotion = (raw_move >> 12) & 0x7;
+
+    board.legal_move_gen().into_iter().find(|m| m.from() == from && match m.infos() {
+        MoveInfo::KingCastle => to == ROOK_CASTLING_START[CastlingRights::index(board.to_move, KINGSIDE)],
+        MoveInfo::QueenCastle => to == ROOK_CASTLING_START[CastlingRights::index(board.to_move, QUEENSIDE)],
+        MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) => m.to() == to && promotion == promotion_code(piece),
+        _ => m.to() == to && promotion == 0,
+    })
+}
+
+pub fn encode_move(board: &Board, m: Move) -> u16 {
+    let to = match m.infos() {
+        MoveInfo::KingCastle => ROOK_CASTLING_START[CastlingRights::index(board.to_move, KINGSIDE)],
+        MoveInfo::QueenCastle => ROOK_CASTLING_START[CastlingRights::index(board.to_move, QUEENSIDE)],
+        _ => m.to(),
+    };
+    let promotion = match m.infos() {
+        MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) => promotion_code(piece),
+        _ => 0,
+    };
+    to as u16 | (m.from() as u16) << 6 | promotion << 12
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct PgnGame {
+    // Set by the FEN tag of games that don't start from the initial position
+    fen: Option<String>,
+    // Main line in SAN
+    moves: Vec<String>,
+    // From white point of view, None for unfinished games
+    result: Option<f64>,
+}
+
+// Comments, variations and numeric annotations are skipped, only the main line is kept
+fn parse_pgn(pgn: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let (mut comment, mut variation_depth) = (false, 0usize);
+
+    for line in pgn.lines() {
+        let line = line.trim();
+        if !comment && variation_depth == 0 && line.starts_with('[') {
+            // A tag after some moves starts a game whose predecessor had no termination marker
+            if !game.moves.is_empty() {
+                games.push(std::mem::take(&mut game));
+            }
+            if let Some(fen) = line.strip_prefix("[FEN \"") {
+                game.fen = Some(fen.trim_end_matches(['"', ']']).to_string());
+            }
+            continue;
+        }
+
+        // Skipped characters are replaced by spaces so that "e4{comment}e5" still gives two moves
+        let mut text = String::with_capacity(line.len());
+        for c in line.chars() {
+            let keep = match c {
+                _ if comment => {
+                    comment = c != '}';
+                    false
+                },
+                '{' => {
+                    comment = true;
+                    false
+                },
+                ';' => break,
+                '(' => {
+                    variation_depth += 1;
+                    false
+                },
+                ')' => {
+                    variation_depth = variation_depth.saturating_sub(1);
+                    false
+                },
+                _ => variation_depth == 0,
+            };
+            text.push(if keep { c } else { ' ' });
+        }
+
+        for token in text.split_ascii_whitespace() {
+            let result = match token {
+                "1-0" => Some(Some(1.0)),
+                "0-1" => Some(Some(0.0)),
+                "1/2-1/2" => Some(Some(0.5)),
+                "*" => Some(None),
+                _ => None,
+            };
+            if let Some(result) = result {
+                game.result = result;
+                games.push(std::mem::take(&mut game));
+                continue;
+            }
+
+            // Move numbers can be glued to the move, as in "1.e4" or "12...Nf6"
+            let token = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+            if !token.is_empty() && !token.starts_with('$') {
+                game.moves.push(token.to_string());
+            }
+        }
+    }
+
+    if !game.moves.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+// Unfinished games are skipped and a game is only followed until its first unreadable move
+fn build_book(games: &[PgnGame], max_plies: usize) -> OpeningBook {
+    let mut weights: HashMap<(u64, u16), u32> = HashMap::new();
+    for game in games {
+        let Some(result) = game.result else { continue };
+        let board = match &game.fen {
+            Some(fen) => Board::from_fen(fen),
+            None => Ok(Board::new()),
+        };
+        let Ok(mut board) = board else { continue };
+
+        for san in game.moves.iter().take(max_plies) {
+            let Ok(m) = board.parse_san(san) else { break };
+            let score = if board.to_move == WHITE { result } else { 1.0 - result };
+            *weights.entry((board.polyglot_key(), encode_move(&board, m))).or_default() += (2.0 * score) as u32;
+            board.make(m);
+        }
+    }
+
+    // Weights are scaled down to fit in 16 bits, moves that never scored are dropped
+    let max_weight = weights.values().copied().max().unwrap_or(0);
+    let scale = max_weight.div_ceil(u16::MAX as u32).max(1);
+    let mut entries: Vec<BookEntry> = weights.into_iter()
+        .filter(|&(_, weight)| weight / scale > 0)
+        .map(|((key, raw_move), weight)| BookEntry { key, raw_move, weight: (weight / scale) as u16, learn: 0 })
+        .collect();
+    // Best moves first within a position as other tools expect
+    entries.sort_by_key(|entry| (entry.key, u16::MAX - entry.weight, entry.raw_move));
+    OpeningBook::from_entries(entries)
+}
+
+// book <pgn file> <book file> [plies]
+pub fn run(mut args: impl Iterator<Item = String>) -> io::Result<()> {
+    let (Some(pgn_path), Some(book_path)) = (args.next(), args.next()) else {
+        eprintln!("usage: yace book <pgn file> <book file> [plies]");
+        return Ok(());
+    };
+    let max_plies = args.next().and_then(|plies| plies.parse().ok()).unwrap_or(DEFAULT_BOOK_PLIES);
+
+    let games = parse_pgn(&fs::read_to_string(pgn_path)?);
+    let book = build_book(&games, max_plies);
+    eprintln!("{} games read, {} book entries", games.len(), book.len());
+    fs::write(book_path, book.to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn entry(board: &Board, uci_move: &str, weight: u16) -> BookEntry {
+        let m = board.parse_uci_move(uci_move).unwrap();
+        BookEntry { key: board.polyglot_key(), raw_move: encode_move(board, m), weight, learn: 0 }
+    }
+
+    #[test]
+    fn test_move_encoding() {
+        // e2e4 in the Polyglot format
+        let board = Board::new();
+        assert_eq!(decode_move(&board, 0x031c), Some(board.parse_uci_move("e2e4").unwrap()));
+
+        // Castling is the king taking its rook
+        let board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1").unwrap();
+        let castle = decode_move(&board, (E1 as u16) << 6 | H1 as u16).unwrap();
+        assert_eq!(castle.infos(), MoveInfo::KingCastle);
+        assert_eq!(castle.to(), G1);
+        let board = Board::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b KQkq - 0 1").unwrap();
+        let castle = decode_move(&board, (E8 as u16) << 6 | A8 as u16).unwrap();
+        assert_eq!(castle.infos(), MoveInfo::QueenCastle);
+        assert_eq!(encode_move(&board, castle), (E8 as u16) << 6 | A8 as u16);
+
+        let board = Board::from_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        for uci_move in ["a7a8n", "a7b8q", "a7b8r"] {
+            let m = board.parse_uci_move(uci_move).unwrap();
+            assert_eq!(decode_move(&board, encode_move(&board, m)), Some(m));
+        }
+        // Illegal moves are not decoded
+        assert_eq!(decode_move(&board, (A7 as u16) << 6 | A8 as u16), None);
+    }
+
+    #[test]
+    fn test_probe() {
+        let start = Board::new();
+        let mut after_e4 = Board::new();
+        after_e4.make(after_e4.parse_uci_move("e2e4").unwrap());
+        let book = OpeningBook::from_bytes(&OpeningBook::from_entries(vec![
+            entry(&after_e4, "c7c5", 5),
+            entry(&start, "d2d4", 10),
+            entry(&start, "e2e4", 30),
+            entry(&start, "a2a3", 0),
+        ]).to_bytes()).unwrap();
+
+        assert_eq!(book.len(), 4);
+        assert_eq!(book.entries(start.polyglot_key()).len(), 3);
+        assert_eq!(book.entries(0).len(), 0);
+        assert_eq!(book.moves(&after_e4), vec![(after_e4.parse_uci_move("c7c5").unwrap(), 5)]);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(book.probe(&start, BookSelection::Best, &mut rng), Some(start.parse_uci_move("e2e4").unwrap()));
+        // Weighted selection plays both moves in proportion, never the move with a zero weight
+        let e4_count = (0..1000).filter(|_| book.probe(&start, BookSelection::Weighted, &mut rng) == Some(start.parse_uci_move("e2e4").unwrap())).count();
+        assert!((650..850).contains(&e4_count), "{e4_count}");
+        assert!((0..100).all(|_| book.probe(&start, BookSelection::Weighted, &mut rng) != Some(start.parse_uci_move("a2a3").unwrap())));
+        assert_eq!(book.probe(&Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap(), BookSelection::Weighted, &mut rng), None);
+
+        assert_eq!(OpeningBook::from_bytes(&[0; 17]).unwrap_err(), BookError::InvalidSize(17));
+    }
+
+    #[test]
+    fn test_build_book() {
+        let pgn = "[Event \"Test\"]\n[Result \"1-0\"]\n\n1. e4 {best by test} e5 (1... c5 2. Nf3) 2. Nf3 $1 Nc6 1-0\n\n\
+            [Result \"1/2-1/2\"]\n1.e4 e5 2.Nc3 1/2-1/2\n\
+            [Result \"*\"]\n1. d4 *\n\
+            [FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]\n1. e4 Kd7 0-1\n";
+        let games = parse_pgn(pgn);
+        assert_eq!(games.len(), 4);
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6"]);
+        assert_eq!(games[1].result, Some(0.5));
+        assert_eq!(games[2].result, None);
+        assert_eq!(games[3].fen.as_deref(), Some("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+
+        let book = build_book(&games, DEFAULT_BOOK_PLIES);
+        let start = Board::new();
+        // e4 won once and drew once, d4 comes from an unfinished game
+        assert_eq!(book.moves(&start), vec![(start.parse_uci_move("e2e4").unwrap(), 3)]);
+        // e5 lost the first game so it only scores from the draw
+        let mut board = Board::new();
+        board.make(board.parse_uci_move("e2e4").unwrap());
+        assert_eq!(book.moves(&board), vec![(board.parse_uci_move("e7e5").unwrap(), 1)]);
+        board.make(board.parse_uci_move("e7e5").unwrap());
+        assert_eq!(book.moves(&board), vec![(board.parse_uci_move("g1f3").unwrap(), 2), (board.parse_uci_move("b1c3").unwrap(), 1)]);
+
+        // Only e4 of the first two games, the first move of the last game lost
+        let book = build_book(&games, 1);
+        assert_eq!(book.len(), 1);
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::Write;
+
+use crate::{board::*, evaluation::{classical_evaluation, Evaluator, Term, Trace}, pawn_structure::PawnHashTable};
+
+// The square tables are split by piece, in piece order
+const ROW_COUNT: usize = 16;
+static ROW_NAMES: [&str; ROW_COUNT] = [
+    "Material",
+    "Queen squares",
+    "Rook squares",
+    "Bishop squares",
+    "Knight squares",
+    "Pawn squares",
+    "King squares",
+    "Doubled pawns",
+    "Isolated pawns",
+    "Backward pawns",
+    "Connected pawns",
+    "Passed pawns",
+    "Blocked passers",
+    "Mobility",
+    "King attack",
+    "King shelter",
+];
+
+// Collects the terms of the classical evaluation
+pub struct EvalTrace {
+    pieces: [Option<Piece>; 64],
+    // (middlegame, endgame) of each row for each color, relative to the color
+    rows: [[(i16, i16); 2]; ROW_COUNT],
+    // (middlegame, endgame) of the terms of each square from white point of view
+    squares: [(i16, i16); 64],
+}
+
+impl Trace for EvalTrace {
+    fn term(&mut self, term: Term, color: Color, sq: Option<Square>, middle_game: i16, end_game: i16) {
+        let row = match term {
+            Term::Material => 0,
+            Term::PieceSquare => 1 + usize::from(sq.and_then(|sq| self.pieces[sq as usize]).expect("square term without a piece")),
+            Term::DoubledPawn => 7,
+            Term::IsolatedPawn => 8,
+            Term::BackwardPawn => 9,
+            Term::ConnectedPawn => 10,
+            Term::PassedPawn => 11,
+            Term::BlockedPassedPawn => 12,
+            Term::Mobility => 13,
+            Term::KingAttack => 14,
+            Term::KingShelter => 15,
+        };
+        let row = &mut self.rows[row][color as usize];
+        row.0 += middle_game;
+        row.1 += end_game;
+
+        if let Some(sq) = sq {
+            let sign = if color == WHITE { 1 } else { -1 };
+            self.squares[sq as usize].0 += sign * middle_game;
+            self.squares[sq as usize].1 += sign * end_game;
+        }
+    }
+}
+
+impl EvalTrace {
+    fn new(board: &Board) -> Self {
+        EvalTrace { pieces: board.squares, rows: [[(0, 0); 2]; ROW_COUNT], squares: [(0, 0); 64] }
+    }
+
+    // Sum of all the terms from white point of view as (middlegame, endgame)
+    fn total(&self) -> (i16, i16) {
+        self.rows.iter().fold((0, 0), |(middle_game, end_game), [white, black]| {
+            (middle_game + white.0 - black.0, end_game + white.1 - black.1)
+        })
+    }
+}
+
+// Breakdown of the classical evaluation by term, side and phase, and of the contribution of each square
+// The evaluation used by the search is given last
+pub fn evaluation_report(board: &Board) -> String {
+    let mut trace = EvalTrace::new(board);
+    let score = classical_evaluation(board, &mut PawnHashTable::new(), &mut trace);
+
+    let mut report = String::new();
+    writeln!(report, "{:<16} | {:>13} | {:>13} | {:>13}", "Term", "White", "Black", "Total").unwrap();
+    writeln!(report, "{:<16} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}", "", "MG", "EG", "MG", "EG", "MG", "EG").unwrap();
+    writeln!(report, "{:-<16}-+-{:-<13}-+-{:-<13}-+-{:-<13}", "", "", "", "").unwrap();
+    for (name, [white, black]) in ROW_NAMES.iter().zip(trace.rows) {
+        writeln!(report, "{name:<16} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}", white.0, white.1, black.0, black.1, white.0 - black.0, white.1 - black.1).unwrap();
+    }
+    let (middle_game, end_game) = trace.total();
+    writeln!(report, "{:-<16}-+-{:-<13}-+-{:-<13}-+-{:-<13}", "", "", "", "").unwrap();
+    writeln!(report, "{:<16} | {:>13} | {:>13} | {middle_game:>6} {end_game:>6}", "Total", "", "").unwrap();
+
+    writeln!(report).unwrap();
+    writeln!(report, "Square contributions from white point of view").unwrap();
+    for rank in (0..8).rev() {
+        let row: String = (0..8).map(|file| {
+            let (middle_game, end_game) = trace.squares[Square::new(file, rank) as usize];
+            format!("{:>6}", board.evaluation.taper(middle_game, end_game))
+        }).collect();
+        writeln!(report, "{} {row}", rank + 1).unwrap();
+    }
+    writeln!(report, "  {}", "abcdefgh".chars().map(|file| format!("{file:>6}")).collect::<String>()).unwrap();
+
+    writeln!(report).unwrap();
+    writeln!(report, "Phase: {}", board.evaluation.phase()).unwrap();
+    writeln!(report, "Classical evaluation: {} (white), {score} (side to move)", board.evaluation.taper(middle_game, end_game)).unwrap();
+    if let Some(nnue) = &board.nnue {
+        writeln!(report, "NNUE evaluation: {} (side to move), used by the search", nnue.score(board.to_move)).unwrap();
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::evaluate;
+
+    #[test]
+    fn test_trace_matches_evaluation() {
+        for fen in ["r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4", "6k1/5p1p/6p1/3P4/8/2n5/5PPP/3R2K1 b - - 0 30"] {
+            let board = Board::from_fen(fen).unwrap();
+            let mut trace = EvalTrace::new(&board);
+            let score = classical_evaluation(&board, &mut PawnHashTable::new(), &mut trace);
+            assert_eq!(score, evaluate(&board, &mut PawnHashTable::new()), "{fen}");
+
+            // The sum of the traced terms is the evaluation, and so is the sum of the squares
+            // as every term comes from a piece
+            let (middle_game, end_game) = trace.total();
+            let white_score = board.evaluation.taper(middle_game, end_game);
+            assert_eq!(if board.to_move == WHITE { white_score } else { -white_score }, score, "{fen}");
+            let squares = trace.squares.iter().fold((0, 0), |total, square| (total.0 + square.0, total.1 + square.1));
+            assert_eq!(squares, (middle_game, end_game), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let board = Board::from_fen("6k1/5p1p/6p1/3P4/8/2n5/5PPP/3R2K1 b - - 0 30").unwrap();
+        let report = evaluation_report(&board);
+        // The d5 pawn is passed and connected to nothing
+        let passed = report.lines().find(|line| line.starts_with("Passed pawns")).unwrap();
+        assert!(passed.split_whitespace().nth(3).unwrap().parse::<i16>().unwrap() > 0);
+        assert!(report.contains(&format!("Classical evaluation: {} (white), {} (side to move)", -evaluate(&board, &mut PawnHashTable::new()), evaluate(&board, &mut PawnHashTable::new()))));
+        assert!(!report.contains("NNUE"));
+    }
+}
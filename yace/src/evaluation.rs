@@ -1,9 +1,11 @@
-use crate::board::*;
+use bit_iter::BitIter;
+
+use crate::{board::{magic_table::{bishop_attack, rook_attack}, *}, pawn_structure::{pawn_attacks, pawn_structure, PawnHashTable}};
 
 // Evaluation is relative to side to move
 
 // Piece square table are shown from black perspective (because it starts with A1 and they are symmetric)
-static PAWN_SQUARE_TABLE: [i16; 64] = [
+static PAWN_SQUARE_TABLE_MIDDLE: [i16; 64] = [
     0,  0,  0,  0,  0,  0,  0,  0,
     5, 10, 10,-20,-20, 10, 10,  5,
     5, -5,-10,  0,  0,-10, -5,  5,
@@ -14,7 +16,18 @@ static PAWN_SQUARE_TABLE: [i16; 64] = [
     0,  0,  0,  0,  0,  0,  0,  0
 ];
 
-static KNIGHT_SQUARE_TABLE: [i16; 64] = [
+static PAWN_SQUARE_TABLE_END: [i16; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    10, 10, 10, 10, 10, 10, 10, 10,
+    10, 10, 10, 10, 10, 10, 10, 10,
+    20, 20, 20, 20, 20, 20, 20, 20,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    0,  0,  0,  0,  0,  0,  0,  0
+];
+
+static KNIGHT_SQUARE_TABLE_MIDDLE: [i16; 64] = [
     -50,-40,-30,-30,-30,-30,-40,-50,
     -40,-20,  0,  0,  0,  0,-20,-40,
     -30,  0, 10, 15, 15, 10,  0,-30,
@@ -25,7 +38,18 @@ static KNIGHT_SQUARE_TABLE: [i16; 64] = [
     -50,-40,-30,-30,-30,-30,-40,-50,
 ];
 
-static BISHOP_SQUARE_TABLE: [i16; 64] = [
+static KNIGHT_SQUARE_TABLE_END: [i16; 64] = [
+    -40,-30,-20,-20,-20,-20,-30,-40,
+    -30,-15,  0,  0,  0,  0,-15,-30,
+    -20,  0, 10, 15, 15, 10,  0,-20,
+    -20,  5, 15, 20, 20, 15,  5,-20,
+    -20,  5, 15, 20, 20, 15,  5,-20,
+    -20,  0, 10, 15, 15, 10,  0,-20,
+    -30,-15,  0,  0,  0,  0,-15,-30,
+    -40,-30,-20,-20,-20,-20,-30,-40,
+];
+
+static BISHOP_SQUARE_TABLE_MIDDLE: [i16; 64] = [
     -20,-10,-10,-10,-10,-10,-10,-20,
     -10,  0,  0,  0,  0,  0,  0,-10,
     -10,  0,  5, 10, 10,  5,  0,-10,
@@ -36,7 +60,18 @@ static BISHOP_SQUARE_TABLE: [i16; 64] = [
     -20,-10,-10,-10,-10,-10,-10,-20,
 ];
 
-static ROOK_SQUARE_TABLE: [i16; 64] = [
+static BISHOP_SQUARE_TABLE_END: [i16; 64] = [
+    -15,-10,-10,-10,-10,-10,-10,-15,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -15,-10,-10,-10,-10,-10,-10,-15,
+];
+
+static ROOK_SQUARE_TABLE_MIDDLE: [i16; 64] = [
     0,  0,  0,  5,  5,  0,  0,  0,
     -5,  0,  0,  0,  0,  0,  0, -5,
     -5,  0,  0,  0,  0,  0,  0, -5,
@@ -47,7 +82,18 @@ static ROOK_SQUARE_TABLE: [i16; 64] = [
     0,  0,  0,  0,  0,  0,  0,  0,
 ];
 
-static QUEEN_SQUARE_TABLE: [i16; 64] = [
+static ROOK_SQUARE_TABLE_END: [i16; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    10, 10, 10, 10, 10, 10, 10, 10,
+    5,  5,  5,  5,  5,  5,  5,  5,
+];
+
+static QUEEN_SQUARE_TABLE_MIDDLE: [i16; 64] = [
     -20,-10,-10, -5, -5,-10,-10,-20,
     -10,  0,  5,  0,  0,  0,  0,-10,
     -10,  5,  5,  5,  5,  5,  0,-10,
@@ -58,6 +104,17 @@ static QUEEN_SQUARE_TABLE: [i16; 64] = [
     -20,-10,-10, -5, -5,-10,-10,-20
 ];
 
+static QUEEN_SQUARE_TABLE_END: [i16; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -5,  0,  5, 10, 10,  5,  0, -5,
+    -5,  0,  5, 10, 10,  5,  0, -5,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
 static KING_SQUARE_TABLE_MIDDLE: [i16; 64] = [
     20, 30, 10,  0,  0, 10, 30, 20,
     20, 20,  0,  0,  0,  0, 20, 20,
@@ -80,68 +137,370 @@ static KING_SQUARE_TABLE_END: [i16; 64] = [
     -50,-40,-30,-20,-20,-30,-40,-50,
 ];
 
+// Material values, indexed by piece (queen, rook, bishop, knight, pawn, king)
+static PIECE_VALUE_MIDDLE: [i16; 6] = [900, 500, 330, 320, 100, 0];
+static PIECE_VALUE_END: [i16; 6] = [950, 550, 320, 300, 120, 0];
+
+// Contribution of each piece to the game phase, indexed by piece
+// Only pawns and kings left is a pure endgame, all the pieces on the board is MAX_PHASE
+static PHASE_WEIGHT: [u8; 6] = [4, 2, 1, 1, 0, 0];
+pub const MAX_PHASE: u8 = 24;
+
+// Material values and square tables as a flat vector for tuning, the middlegame parameters then the endgame ones
+// For each phase the 6 piece values come first, then the 6 square tables, both indexed by piece
+pub const PARAMETERS_PER_PHASE: usize = 6 + 6 * 64;
+pub const PARAMETER_COUNT: usize = 2 * PARAMETERS_PER_PHASE;
+
+// Each safe square above the baseline is worth the weight, indexed by piece
+static MOBILITY_BASELINE: [i16; 6] = [13, 7, 6, 4, 0, 0];
+static MOBILITY_WEIGHT_MIDDLE: [i16; 6] = [1, 2, 5, 4, 0, 0];
+static MOBILITY_WEIGHT_END: [i16; 6] = [2, 4, 5, 4, 0, 0];
+
+// Units of danger for each attacked square of the enemy king zone, indexed by piece
+static KING_ATTACK_WEIGHT: [i32; 6] = [5, 3, 2, 2, 0, 0];
+// A lone attacker is rarely dangerous
+const MIN_KING_ATTACKERS: u32 = 2;
+const MAX_KING_DANGER: i32 = 500;
+
+// King shelter only matters in the middlegame
+const SHIELD_PAWN_ADVANCED_PENALTY: i16 = -10;
+const SHIELD_PAWN_MISSING_PENALTY: i16 = -25;
+const SEMI_OPEN_FILE_NEAR_KING_PENALTY: i16 = -10;
+const OPEN_FILE_NEAR_KING_PENALTY: i16 = -20;
+
 // This is from white point of view
+// Middlegame and endgame scores are tracked separately and interpolated with the game phase
 #[derive(Debug, Clone)]
 pub struct IncrementalEval {
-    material_evaluation: i16,
-    position_evaluation: i16,
+    middle_game_evaluation: i16,
+    end_game_evaluation: i16,
+    phase: u8,
+}
+
+fn square_tables(piece: Piece) -> (&'static [i16; 64], &'static [i16; 64]) {
+    match piece {
+        Piece::Pawn => (&PAWN_SQUARE_TABLE_MIDDLE, &PAWN_SQUARE_TABLE_END),
+        Piece::Knight => (&KNIGHT_SQUARE_TABLE_MIDDLE, &KNIGHT_SQUARE_TABLE_END),
+        Piece::Bishop => (&BISHOP_SQUARE_TABLE_MIDDLE, &BISHOP_SQUARE_TABLE_END),
+        Piece::Rook => (&ROOK_SQUARE_TABLE_MIDDLE, &ROOK_SQUARE_TABLE_END),
+        Piece::Queen => (&QUEEN_SQUARE_TABLE_MIDDLE, &QUEEN_SQUARE_TABLE_END),
+        Piece::King => (&KING_SQUARE_TABLE_MIDDLE, &KING_SQUARE_TABLE_END),
+    }
 }
 
-fn piece_positional_value(piece: Piece, mut sq: Square, color: Color) -> i16 {
+// Material and position of the piece as (middlegame, endgame)
+fn piece_value(piece: Piece, mut sq: Square, color: Color) -> (i16, i16) {
     if color == BLACK {
         sq = sq.vertical_symmetry();
     }
-    match piece {
-        Piece::Pawn => PAWN_SQUARE_TABLE[sq as usize],
-        Piece::Knight => KNIGHT_SQUARE_TABLE[sq as usize],
-        Piece::Bishop => BISHOP_SQUARE_TABLE[sq as usize],
-        Piece::Rook => ROOK_SQUARE_TABLE[sq as usize],
-        Piece::Queen => QUEEN_SQUARE_TABLE[sq as usize],
-        Piece::King => KING_SQUARE_TABLE_MIDDLE[sq as usize], // TODO handle endgame
+    let (middle_game_table, end_game_table) = square_tables(piece);
+    let index = usize::from(piece);
+    (PIECE_VALUE_MIDDLE[index] + middle_game_table[sq as usize], PIECE_VALUE_END[index] + end_game_table[sq as usize])
+}
+
+// Current values of the tunable parameters
+pub fn parameters() -> Vec<i16> {
+    let mut parameters = Vec::with_capacity(PARAMETER_COUNT);
+    for (piece_values, end_game) in [(&PIECE_VALUE_MIDDLE, false), (&PIECE_VALUE_END, true)] {
+        parameters.extend(piece_values);
+        for piece in [QUEEN, ROOK, BISHOP, KNIGHT, PAWN, KING] {
+            let (middle_game_table, end_game_table) = square_tables(piece);
+            parameters.extend(if end_game { end_game_table } else { middle_game_table });
+        }
+    }
+    parameters
+}
+
+// Indices of the value and square parameters of the piece in the middlegame
+// The endgame ones are PARAMETERS_PER_PHASE further
+pub fn parameter_indices(piece: Piece, mut sq: Square, color: Color) -> (usize, usize) {
+    if color == BLACK {
+        sq = sq.vertical_symmetry();
+    }
+    let index = usize::from(piece);
+    (index, 6 + index * 64 + sq as usize)
+}
+
+// Evaluation updated by the board each time a piece is added, removed or moved
+pub trait Evaluator {
+    fn add_piece(&mut self, piece: Piece, sq: Square, color: Color);
+    fn remove_piece(&mut self, piece: Piece, sq: Square, color: Color);
+    fn move_piece(&mut self, piece: Piece, from: Square, to: Square, color: Color);
+    // Relative to pov
+    fn score(&self, pov: Color) -> i16;
+}
+
+impl Evaluator for IncrementalEval {
+    fn add_piece(&mut self, piece: Piece, sq: Square, color: Color) {
+        self.add_value(piece, sq, color);
+        self.phase += PHASE_WEIGHT[usize::from(piece)];
+    }
+
+    fn remove_piece(&mut self, piece: Piece, sq: Square, color: Color) {
+        self.remove_value(piece, sq, color);
+        self.phase -= PHASE_WEIGHT[usize::from(piece)];
+    }
+
+    fn move_piece(&mut self, piece: Piece, from: Square, to: Square, color: Color) {
+        self.remove_value(piece, from, color);
+        self.add_value(piece, to, color);
+    }
+
+    fn score(&self, pov: Color) -> i16 {
+        let white_score = self.taper(self.middle_game_evaluation, self.end_game_evaluation);
+        if pov == WHITE {
+            white_score
+        } else {
+            -white_score
+        }
     }
 }
 
 impl IncrementalEval {
     pub fn new() -> Self {
-        IncrementalEval { material_evaluation: 0, position_evaluation: 0 }
+        IncrementalEval { middle_game_evaluation: 0, end_game_evaluation: 0, phase: 0 }
     }
 
-    pub fn move_piece(&mut self, piece: Piece, from: Square, to: Square, color: Color) {
+    fn add_value(&mut self, piece: Piece, sq: Square, color: Color) {
+        let (middle_game, end_game) = piece_value(piece, sq, color);
         if color == WHITE {
-            self.position_evaluation -= piece_positional_value(piece, from, color);
-            self.position_evaluation += piece_positional_value(piece, to, color);
+            self.middle_game_evaluation += middle_game;
+            self.end_game_evaluation += end_game;
         } else {
-            self.position_evaluation += piece_positional_value(piece, from, color);
-            self.position_evaluation -= piece_positional_value(piece, to, color);
+            self.middle_game_evaluation -= middle_game;
+            self.end_game_evaluation -= end_game;
         }
     }
 
-    pub fn add_piece(&mut self, piece: Piece, sq: Square, color: Color) {
+    fn remove_value(&mut self, piece: Piece, sq: Square, color: Color) {
+        let (middle_game, end_game) = piece_value(piece, sq, color);
         if color == WHITE {
-            self.material_evaluation += piece.value() as i16;
-            self.position_evaluation += piece_positional_value(piece, sq, color);
+            self.middle_game_evaluation -= middle_game;
+            self.end_game_evaluation -= end_game;
         } else {
-            self.material_evaluation -= piece.value() as i16;
-            self.position_evaluation -= piece_positional_value(piece, sq, color);
+            self.middle_game_evaluation += middle_game;
+            self.end_game_evaluation += end_game;
         }
     }
 
-    pub fn remove_piece(&mut self, piece: Piece, sq: Square, color: Color) {
-        if color == WHITE {
-            self.material_evaluation -= piece.value() as i16;
-            self.position_evaluation -= piece_positional_value(piece, sq, color);
-        } else {
-            self.material_evaluation += piece.value() as i16;
-            self.position_evaluation += piece_positional_value(piece, sq, color);
+    // Promotions can bring the phase above MAX_PHASE
+    pub fn phase(&self) -> u8 {
+        self.phase.min(MAX_PHASE)
+    }
+
+    // Interpolates between the middlegame and the endgame score with the current phase
+    pub fn taper(&self, middle_game: i16, end_game: i16) -> i16 {
+        let phase = self.phase() as i32;
+        ((middle_game as i32 * phase + end_game as i32 * (MAX_PHASE as i32 - phase)) / MAX_PHASE as i32) as i16
+    }
+}
+
+// Terms of the classical evaluation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Term {
+    Material,
+    PieceSquare,
+    DoubledPawn,
+    IsolatedPawn,
+    BackwardPawn,
+    ConnectedPawn,
+    PassedPawn,
+    BlockedPassedPawn,
+    Mobility,
+    KingAttack,
+    KingShelter,
+}
+
+// Receives the contribution of each term to the score of color as (middlegame, endgame)
+// The square is the one of the piece responsible for it, if any
+pub trait Trace {
+    // Incremental and cached terms are recomputed only when the trace is enabled
+    const ENABLED: bool = true;
+
+    fn term(&mut self, term: Term, color: Color, sq: Option<Square>, middle_game: i16, end_game: i16);
+}
+
+// Used by the search, nothing is traced
+impl Trace for () {
+    const ENABLED: bool = false;
+
+    fn term(&mut self, _term: Term, _color: Color, _sq: Option<Square>, _middle_game: i16, _end_game: i16) {}
+}
+
+// The network is used when one is loaded, otherwise the classical evaluation
+// The result is relative to side to move
+pub fn evaluate(board: &Board, pawn_table: &mut PawnHashTable) -> i16 {
+    if let Some(nnue) = &board.nnue {
+        return nnue.score(board.to_move);
+    }
+    classical_evaluation(board, pawn_table, &mut ())
+}
+
+// Incremental evaluation completed with the terms that are computed from scratch
+// The result is relative to side to move
+pub fn classical_evaluation<T: Trace>(board: &Board, pawn_table: &mut PawnHashTable, trace: &mut T) -> i16 {
+    if T::ENABLED {
+        trace_pieces(board, trace);
+    }
+    let (mut middle_game, mut end_game) = pawn_structure(board, pawn_table, trace);
+
+    let (white_middle, white_end, black_king_danger) = mobility_and_king_attack(board, WHITE, trace);
+    let (black_middle, black_end, white_king_danger) = mobility_and_king_attack(board, BLACK, trace);
+    middle_game += white_middle - black_middle - white_king_danger + black_king_danger;
+    end_game += white_end - black_end;
+    middle_game += king_shelter::<WHITE>(board, trace) - king_shelter::<BLACK>(board, trace);
+
+    let evaluation = &board.evaluation;
+    let white_score = evaluation.taper(evaluation.middle_game_evaluation + middle_game, evaluation.end_game_evaluation + end_game);
+    if board.to_move == WHITE { white_score } else { -white_score }
+}
+
+// The incremental evaluation is the sum of the material and square values of every piece
+fn trace_pieces<T: Trace>(board: &Board, trace: &mut T) {
+    for sq in 0..64 {
+        if let Some(piece) = board.squares[sq as usize] {
+            let color = if board.pieces[WHITE].has(sq) { WHITE } else { BLACK };
+            let relative_square = if color == WHITE { sq } else { sq.vertical_symmetry() } as usize;
+            let (middle_game_table, end_game_table) = square_tables(piece);
+            let index = usize::from(piece);
+            trace.term(Term::Material, color, Some(sq), PIECE_VALUE_MIDDLE[index], PIECE_VALUE_END[index]);
+            trace.term(Term::PieceSquare, color, Some(sq), middle_game_table[relative_square], end_game_table[relative_square]);
         }
     }
+}
 
-    pub fn score(&self, pov: Color) -> i16 {
-        let white_score = self.material_evaluation + self.position_evaluation;
-        if pov == WHITE {
-            white_score
-        } else {
-            -white_score
+fn piece_attacks(piece: Piece, sq: Square, occupancy: Bitboard) -> Bitboard {
+    match piece {
+        Piece::Knight => KNIGHT_ATTACK[sq as usize],
+        Piece::Bishop => bishop_attack(sq, occupancy),
+        Piece::Rook => rook_attack(sq, occupancy),
+        Piece::Queen => bishop_attack(sq, occupancy) | rook_attack(sq, occupancy),
+        Piece::Pawn | Piece::King => EMPTY,
+    }
+}
+
+// Mobility of the pieces of color as (middlegame, endgame) and the danger they create for the enemy king
+// Squares occupied by our pieces or attacked by enemy pawns are not counted as safe
+fn mobility_and_king_attack<T: Trace>(board: &Board, color: Color, trace: &mut T) -> (i16, i16, i16) {
+    let occupancy = board.occupancy();
+    let safe_squares = !board.pieces[color] & !pawn_attacks(board.bitboards[PAWN] & board.pieces[!color], !color);
+    let enemy_king = (board.bitboards[KING] & board.pieces[!color]).lsb();
+    let king_zone = KING_ATTACK[enemy_king as usize] | enemy_king.as_bitboard();
+
+    let (mut middle_game, mut end_game) = (0, 0);
+    let (mut attackers, mut attack_units) = (0, 0);
+    for piece in [QUEEN, ROOK, BISHOP, KNIGHT] {
+        let index = usize::from(piece);
+        for sq in BitIter::from(board.bitboards[piece] & board.pieces[color]) {
+            let attacks = piece_attacks(piece, sq as Square, occupancy);
+            let mobility = (attacks & safe_squares).count_ones() as i16 - MOBILITY_BASELINE[index];
+            middle_game += mobility * MOBILITY_WEIGHT_MIDDLE[index];
+            end_game += mobility * MOBILITY_WEIGHT_END[index];
+            trace.term(Term::Mobility, color, Some(sq as Square), mobility * MOBILITY_WEIGHT_MIDDLE[index], mobility * MOBILITY_WEIGHT_END[index]);
+
+            let zone_attacks = attacks & king_zone;
+            if zone_attacks != EMPTY {
+                attackers += 1;
+                attack_units += KING_ATTACK_WEIGHT[index] * zone_attacks.count_ones() as i32;
+            }
+        }
+    }
+
+    // The danger grows quadratically with the attack units
+    let king_danger = if attackers >= MIN_KING_ATTACKERS { (attack_units * attack_units / 4).min(MAX_KING_DANGER) } else { 0 } as i16;
+    trace.term(Term::KingAttack, !color, Some(enemy_king), -king_danger, 0);
+    (middle_game, end_game, king_danger)
+}
+
+// Missing or advanced pawns in front of the king and open files around it, as a middlegame penalty
+fn king_shelter<const COLOR: bool>(board: &Board, trace: &mut impl Trace) -> i16 {
+    let king_square = (board.bitboards[KING] & board.pieces[COLOR]).lsb();
+    let own_pawns = board.bitboards[PAWN] & board.pieces[COLOR];
+    let enemy_pawns = board.bitboards[PAWN] & board.pieces[!COLOR];
+    let first_rank = RANKS[king_square.rank() as usize].forward::<COLOR>();
+    let second_rank = first_rank.forward::<COLOR>();
+
+    let mut penalty = 0;
+    for file in (king_square.file() - 1).max(0)..=(king_square.file() + 1).min(7) {
+        let file_pawns = own_pawns & FILES[file as usize];
+        if file_pawns & first_rank == EMPTY {
+            penalty += if file_pawns & second_rank != EMPTY { SHIELD_PAWN_ADVANCED_PENALTY } else { SHIELD_PAWN_MISSING_PENALTY };
+        }
+        if file_pawns == EMPTY {
+            penalty += if enemy_pawns & FILES[file as usize] == EMPTY { OPEN_FILE_NEAR_KING_PENALTY } else { SEMI_OPEN_FILE_NEAR_KING_PENALTY };
         }
     }
-}
\ No newline at end of file
+    trace.term(Term::KingShelter, COLOR, Some(king_square), penalty, 0);
+    penalty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase() {
+        let board = Board::new();
+        assert_eq!(board.evaluation.phase(), MAX_PHASE);
+        assert_eq!(board.evaluation.score(WHITE), 0);
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.evaluation.phase(), 0);
+
+        let board = Board::from_fen("3qk3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert_eq!(board.evaluation.phase(), 8);
+    }
+
+    #[test]
+    fn test_tapered_king() {
+        // The king belongs to the center in the endgame but not in the middlegame
+        let center = Board::from_fen("4k3/8/8/8/4K3/8/8/8 w - - 0 1").unwrap();
+        let corner = Board::from_fen("4k3/8/8/8/8/8/8/6K1 w - - 0 1").unwrap();
+        assert!(center.evaluation.score(WHITE) > corner.evaluation.score(WHITE));
+
+        let center = Board::from_fen("rnbqkbnr/pppppppp/8/8/4K3/8/PPPPPPPP/RNBQ1BNR w kq - 0 1").unwrap();
+        assert!(center.evaluation.score(WHITE) < Board::new().evaluation.score(WHITE));
+        assert_eq!(center.evaluation.score(BLACK), -center.evaluation.score(WHITE));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut pawn_table = PawnHashTable::new();
+        assert_eq!(evaluate(&Board::new(), &mut pawn_table), 0);
+
+        // The passed pawn on e5 is worth more than its material and square
+        let board = Board::from_fen("4k3/8/8/4P3/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(evaluate(&board, &mut pawn_table) > board.evaluation.score(WHITE));
+        let board = Board::from_fen("4k3/8/8/4P3/8/8/8/4K3 b - - 0 1").unwrap();
+        assert!(evaluate(&board, &mut pawn_table) < board.evaluation.score(BLACK));
+    }
+
+    #[test]
+    fn test_mobility() {
+        let center = Board::from_fen("4k3/8/8/8/4N3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(mobility_and_king_attack(&center, WHITE, &mut ()), (16, 16, 0));
+        let corner = Board::from_fen("4k3/8/8/8/8/8/8/N3K3 w - - 0 1").unwrap();
+        assert_eq!(mobility_and_king_attack(&corner, WHITE, &mut ()), (-8, -8, 0));
+
+        // c5 is attacked by an enemy pawn so it is not safe for the knight
+        let board = Board::from_fen("4k3/8/1p6/8/4N3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(mobility_and_king_attack(&board, WHITE, &mut ()), (12, 12, 0));
+    }
+
+    #[test]
+    fn test_king_safety() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(king_shelter::<WHITE>(&board, &mut ()), 0);
+        assert_eq!(king_shelter::<BLACK>(&board, &mut ()), 0);
+
+        let board = Board::from_fen("6k1/5p1p/6p1/8/8/8/5P1P/6K1 w - - 0 1").unwrap();
+        assert_eq!(king_shelter::<BLACK>(&board, &mut ()), SHIELD_PAWN_ADVANCED_PENALTY);
+        assert_eq!(king_shelter::<WHITE>(&board, &mut ()), SHIELD_PAWN_MISSING_PENALTY + SEMI_OPEN_FILE_NEAR_KING_PENALTY);
+
+        // The queen and the knight both attack g7, a single attacker is ignored
+        let board = Board::from_fen("6k1/5ppp/8/6QN/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(mobility_and_king_attack(&board, WHITE, &mut ()).2, 7 * 7 / 4);
+        let board = Board::from_fen("6k1/5ppp/8/6Q1/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(mobility_and_king_attack(&board, WHITE, &mut ()).2, 0);
+    }
+}
@@ -1,12 +1,35 @@
 use board::Board;
+use uci::Uci;
 
 mod board;
+mod book;
 mod move_ordering;
 mod search;
 mod evaluation;
+mod eval_trace;
+mod pawn_structure;
+mod nnue;
+mod syzygy;
+mod transposition;
+mod time_manager;
+mod tune;
+mod uci;
 
 fn main() {
-    benchmark_perft();
+    match std::env::args().nth(1).as_deref() {
+        Some("perft") => benchmark_perft(),
+        Some("book") => {
+            if let Err(err) = book::run(std::env::args().skip(2)) {
+                eprintln!("book building failed: {err}");
+            }
+        },
+        Some("tune") => {
+            if let Err(err) = tune::run(std::env::args().skip(2)) {
+                eprintln!("tuning failed: {err}");
+            }
+        },
+        _ => Uci::new().run(),
+    }
 }
 
 fn benchmark_perft() {
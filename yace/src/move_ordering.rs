@@ -1,42 +1,215 @@
 use std::cmp::max;
 
 use arrayvec::ArrayVec;
-use crate::board::*;
+use bit_iter::BitIter;
+use crate::{board::*, search::MAX_PLY};
+
+// History scores stay within [-MAX_HISTORY, MAX_HISTORY]
+pub const MAX_HISTORY: i16 = 16384;
+
+// Butterfly history, how often a quiet move caused a beta cutoff, indexed by color, from and to
+pub struct HistoryTable {
+    table: Box<[[[i16; 64]; 64]; 2]>,
+}
+
+impl HistoryTable {
+    pub fn new() -> Self {
+        HistoryTable { table: Box::new([[[0; 64]; 64]; 2]) }
+    }
+
+    pub fn get(&self, color: Color, m: Move) -> i16 {
+        self.table[color as usize][m.from() as usize][m.to() as usize]
+    }
+
+    pub fn update(&mut self, color: Color, m: Move, bonus: i16) {
+        apply_bonus(&mut self.table[color as usize][m.from() as usize][m.to() as usize], bonus);
+    }
+}
+
+impl Default for HistoryTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The bonus is scaled down as the entry gets closer to the bounds so it never overflows
+fn apply_bonus(entry: &mut i16, bonus: i16) {
+    let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY) as i32;
+    *entry += (bonus - *entry as i32 * bonus.abs() / MAX_HISTORY as i32) as i16;
+}
+
+// Everything learnt from beta cutoffs during the search to order the quiet moves
+pub struct OrderingTables {
+    pub history: HistoryTable,
+    // Two quiet moves per ply that recently caused a cutoff in a sibling node
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    // Quiet reply that refuted the previous move, indexed by its from and to
+    countermoves: Box<[[Option<Move>; 64]; 64]>,
+    // History of a quiet move following the previous move, indexed by previous piece and to then piece and to
+    continuation_history: Box<[[[[i16; 64]; 6]; 64]; 6]>,
+}
+
+impl OrderingTables {
+    pub fn new() -> Self {
+        OrderingTables {
+            history: HistoryTable::new(),
+            killers: [[None; 2]; MAX_PLY],
+            countermoves: Box::new([[None; 64]; 64]),
+            continuation_history: Box::new([[[[0; 64]; 6]; 64]; 6]),
+        }
+    }
+
+    pub fn killers(&self, ply: u8) -> [Option<Move>; 2] {
+        self.killers[ply as usize]
+    }
+
+    pub fn countermove(&self, previous_move: Option<Move>) -> Option<Move> {
+        previous_move.and_then(|previous| self.countermoves[previous.from() as usize][previous.to() as usize])
+    }
+
+    // The board must be the one before m is made, the previous move being already made
+    fn continuation_index(board: &Board, previous_move: Option<Move>, m: Move) -> Option<[usize; 4]> {
+        let previous = previous_move?;
+        let previous_piece = board.squares[previous.to() as usize]?;
+        let piece = board.squares[m.from() as usize]?;
+        Some([previous_piece.into(), previous.to() as usize, piece.into(), m.to() as usize])
+    }
+
+    pub fn quiet_score(&self, board: &Board, previous_move: Option<Move>, m: Move) -> i32 {
+        let continuation = Self::continuation_index(board, previous_move, m)
+            .map_or(0, |[previous_piece, previous_to, piece, to]| self.continuation_history[previous_piece][previous_to][piece][to]);
+        self.history.get(board.to_move, m) as i32 + continuation as i32
+    }
+
+    // The cutoff move gets a bonus, the quiet moves searched before it a malus
+    pub fn update_quiet_cutoff(&mut self, board: &Board, ply: u8, previous_move: Option<Move>, cutoff_move: Move, searched_quiets: &[Move], depthleft: u8) {
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(cutoff_move) {
+            killers[1] = killers[0];
+            killers[0] = Some(cutoff_move);
+        }
+
+        if let Some(previous) = previous_move {
+            self.countermoves[previous.from() as usize][previous.to() as usize] = Some(cutoff_move);
+        }
+
+        let bonus = (depthleft as i16).saturating_mul(depthleft as i16);
+        let color = board.to_move;
+        let maluses = searched_quiets.iter().map(|&m| (m, -bonus));
+        for (m, bonus) in [(cutoff_move, bonus)].into_iter().chain(maluses) {
+            self.history.update(color, m, bonus);
+            if let Some([previous_piece, previous_to, piece, to]) = Self::continuation_index(board, previous_move, m) {
+                apply_bonus(&mut self.continuation_history[previous_piece][previous_to][piece][to], bonus);
+            }
+        }
+    }
+}
+
+impl Default for OrderingTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Material balance of the exchange on the destination square started by m, both sides
+// being allowed to stop capturing when it is not in their interest anymore
+// Pinned pieces only capture along their pin line and the king only captures an undefended piece
+pub fn static_exchange_evaluation(board: &Board, m: Move) -> i16 {
+    let to = m.to();
+    if matches!(m.infos(), MoveInfo::KingCastle | MoveInfo::QueenCastle) {
+        return 0;
+    }
 
-// TODO pinned pieces are still able to capture here
-pub fn static_exchange_evaluation(board: &Board, from: Square, to: Square) -> i16 {
     let mut gain: ArrayVec<i16, 32> = ArrayVec::new();
-    let mut occupancy = board.occupancy();
-    let mut capturing = Some((from.as_bitboard(), board.squares[from as usize].unwrap()));
-    let mut playing_color = board.to_move;
+    let mut occupancy = board.occupancy().unset(m.from());
+    if m.infos() == MoveInfo::EnPassantCapture {
+        occupancy = occupancy.unset(if board.to_move == WHITE {to.backward::<WHITE>()} else {to.backward::<BLACK>()});
+    }
 
-    let mut both_color_attackers = board.square_full_attacked_by(to, occupancy);
-    let mut alread_seen_attackers = EMPTY;
+    // Pinned pieces can still capture the pinner or anything else on the pin line
+    let mut pinned = EMPTY;
+    for color in [WHITE, BLACK] {
+        let king_square = (board.bitboards[KING] & board.pieces[color]).lsb();
+        for sq in BitIter::from(board.pinned_pieces_of(color) & board.pieces[color]) {
+            if !Bitboard::line(sq as Square, king_square).has(to) {
+                pinned |= (sq as Square).as_bitboard();
+            }
+        }
+    }
 
     let may_cause_xray = board.bitboards[PAWN] | board.bitboards[BISHOP] | board.bitboards[ROOK] | board.bitboards[QUEEN];
+    let mut attackers = board.square_full_attacked_by(to, occupancy) & occupancy & !pinned;
 
-    let target_piece = board.squares[to as usize].unwrap();
-    gain.push(target_piece.value() as i16);
-
-    while let Some((from_bb, from_piece)) = capturing {
-        gain.push(from_piece.value() as i16 - gain.last().unwrap());
-        alread_seen_attackers |= from_bb;
+    gain.push(captured_value(board, m) + promotion_gain(m));
+    let mut on_square_value = match m.infos() {
+        MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) => piece.value(),
+        _ => board.squares[m.from() as usize].unwrap().value(),
+    } as i16;
+    let mut playing_color = board.to_move;
 
+    loop {
         playing_color = !playing_color;
+        let Some((from_bb, from_piece)) = least_valuable_attacker(board, attackers, playing_color) else { break };
+        if from_piece == KING && attackers & board.pieces[!playing_color] != EMPTY {
+            break;
+        }
+
+        // Recapturing pawns reaching the last rank promote to a queen
+        let promotion = from_piece == PAWN && (RANK1 | RANK8).has(to);
+        let promotion_gain = if promotion {(QUEEN.value() - PAWN.value()) as i16} else {0};
+        gain.push(on_square_value + promotion_gain - gain.last().unwrap());
+        on_square_value = if promotion {QUEEN.value() as i16} else {from_piece.value() as i16};
+
         occupancy &= !from_bb;
-        both_color_attackers &= !from_bb;
+        attackers &= !from_bb;
         if may_cause_xray & from_bb != EMPTY {
-            both_color_attackers |= board.square_xray_update(to, occupancy) & !alread_seen_attackers;
+            attackers |= board.square_xray_update(to, occupancy) & occupancy & !pinned;
         }
-
-        capturing = least_valuable_attacker(board, both_color_attackers, playing_color);
     }
-    
-    for i in (1..gain.len()-1).rev() {
+
+    for i in (1..gain.len()).rev() {
         gain[i-1] = -max(-gain[i-1], gain[i]);
     }
 
-    *gain.first().unwrap()
+    gain[0]
+}
+
+// Whether the exchange started by m wins at least threshold, without a full evaluation
+// when the result doesn't depend on the replies
+pub fn see_ge(board: &Board, m: Move, threshold: i16) -> bool {
+    let best_case = captured_value(board, m) + promotion_gain(m);
+    if best_case < threshold {
+        return false;
+    }
+
+    let moving_value = match m.infos() {
+        MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) => piece.value(),
+        _ => board.squares[m.from() as usize].unwrap().value(),
+    } as i16;
+    // The first recapture can be made by a pawn promoting on its last rank
+    let enemy_promotion_rank = if board.to_move == WHITE {RANK1} else {RANK8};
+    let pawn_recapture = enemy_promotion_rank.has(m.to())
+        && board.square_full_attacked_by(m.to(), board.occupancy()) & board.bitboards[PAWN] & board.pieces[!board.to_move] != EMPTY;
+    let worst_case = best_case - moving_value - if pawn_recapture {(QUEEN.value() - PAWN.value()) as i16} else {0};
+    if worst_case >= threshold {
+        return true;
+    }
+
+    static_exchange_evaluation(board, m) >= threshold
+}
+
+fn captured_value(board: &Board, m: Move) -> i16 {
+    match m.infos() {
+        MoveInfo::EnPassantCapture => PAWN.value() as i16,
+        _ => board.squares[m.to() as usize].map_or(0, |piece| piece.value() as i16),
+    }
+}
+
+fn promotion_gain(m: Move) -> i16 {
+    match m.infos() {
+        MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) => (piece.value() - PAWN.value()) as i16,
+        _ => 0,
+    }
 }
 
 fn least_valuable_attacker(board: &Board, both_color_attacker: Bitboard, attacking_color: Color) -> Option<(Bitboard, Piece)> {
@@ -50,36 +223,185 @@ fn least_valuable_attacker(board: &Board, both_color_attacker: Bitboard, attacki
     None
 }
 
-fn move_sorting_key(board: &Board, m: Move, last_moved_piece: Square) -> (u8, i16) {
-    let see = static_exchange_evaluation(board, m.from(), m.to());
-    let capture = matches!(m.infos(), MoveInfo::Capture | MoveInfo::CapturePromotion(_));
-    let from_piece = board.squares[m.from() as usize].unwrap();
+type ScoredMoves = ArrayVec<(Move, i32), MAX_MOVE_NUMBER>;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    HashMove,
+    GoodCaptures,
+    Refutations,
+    Quiets,
+    BadCaptures,
+    Evasions,
+    Done,
+}
 
-    // recapture
-    if m.to() == last_moved_piece {
-        (1, from_piece.value() as i16)
+// Yields the moves of a node best first, generating and scoring each kind of move only when it is reached
+// so that nothing more than the hash move is generated when it causes a cutoff
+// Order: hash move, captures not losing material by MVV-LVA, killers and countermove,
+// quiet moves by history, losing captures. When in check all the evasions are scored together
+pub struct MovePicker {
+    stage: Stage,
+    // Losing captures are skipped in quiescence
+    captures_only: bool,
+    previous_move: Option<Move>,
+    hash_move: Option<Move>,
+    refutations: [Option<Move>; 3],
+    refutation_index: usize,
+    captures: Option<ScoredMoves>,
+    quiets: Option<ScoredMoves>,
+    evasions: Option<ScoredMoves>,
+    bad_captures: ArrayVec<Move, MAX_MOVE_NUMBER>,
+    bad_capture_index: usize,
+}
+
+impl MovePicker {
+    // The previous move is the one the opponent just played, None at the root or after a null move
+    pub fn new(board: &Board, tables: &OrderingTables, ply: u8, previous_move: Option<Move>, hash_move: Option<Move>) -> Self {
+        let [first_killer, second_killer] = tables.killers(ply);
+        MovePicker {
+            stage: if board.in_check() {Stage::Evasions} else {Stage::HashMove},
+            captures_only: false,
+            previous_move,
+            hash_move,
+            refutations: [first_killer, second_killer, tables.countermove(previous_move)],
+            refutation_index: 0,
+            captures: None,
+            quiets: None,
+            evasions: None,
+            bad_captures: ArrayVec::new(),
+            bad_capture_index: 0,
+        }
     }
-    // SEE > 0
-    else if see > 0 {
-        (2, -see)
+
+    // Only captures and queen promotions not losing material, or all the evasions when in check
+    pub fn new_quiescence(board: &Board) -> Self {
+        MovePicker {
+            stage: if board.in_check() {Stage::Evasions} else {Stage::GoodCaptures},
+            captures_only: true,
+            previous_move: None,
+            hash_move: None,
+            refutations: [None; 3],
+            refutation_index: 0,
+            captures: None,
+            quiets: None,
+            evasions: None,
+            bad_captures: ArrayVec::new(),
+            bad_capture_index: 0,
+        }
     }
-    // SEE = 0
-    else if capture && see == 0 {
-        (3, from_piece.value() as i16)
+
+    // The board must be in the same position at each call
+    pub fn next(&mut self, board: &Board, tables: &OrderingTables) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GoodCaptures;
+                    // The hash move comes from another position in case of a collision
+                    if let Some(hash_move) = self.hash_move
+                    && board.is_legal(hash_move) {
+                        return Some(hash_move);
+                    }
+                },
+                Stage::GoodCaptures => {
+                    while let Some(m) = pick_best(self.captures(board)) {
+                        if Some(m) == self.hash_move {
+                            continue;
+                        }
+                        if !see_ge(board, m, 0) {
+                            if !self.captures_only {
+                                self.bad_captures.push(m);
+                            }
+                            continue;
+                        }
+                        return Some(m);
+                    }
+                    self.stage = if self.captures_only {Stage::Done} else {Stage::Refutations};
+                },
+                Stage::Refutations => {
+                    while self.refutation_index < self.refutations.len() {
+                        let refutation = self.refutations[self.refutation_index];
+                        self.refutation_index += 1;
+                        if let Some(m) = refutation
+                        && Some(m) != self.hash_move
+                        && !self.refutations[..self.refutation_index-1].contains(&refutation)
+                        && !is_capture_kind(m)
+                        && board.is_legal(m) {
+                            return Some(m);
+                        }
+                    }
+                    self.stage = Stage::Quiets;
+                },
+                Stage::Quiets => {
+                    let (hash_move, refutations) = (self.hash_move, self.refutations);
+                    while let Some(m) = pick_best(self.quiets(board, tables)) {
+                        if Some(m) != hash_move && !refutations.contains(&Some(m)) {
+                            return Some(m);
+                        }
+                    }
+                    self.stage = Stage::BadCaptures;
+                },
+                Stage::BadCaptures => {
+                    if let Some(&m) = self.bad_captures.get(self.bad_capture_index) {
+                        self.bad_capture_index += 1;
+                        return Some(m);
+                    }
+                    self.stage = Stage::Done;
+                },
+                Stage::Evasions => {
+                    if self.evasions.is_none() {
+                        let evasions = board.legal_move_gen().into_iter().map(|m| {
+                            let score = if Some(m) == self.hash_move {
+                                i32::MAX
+                            } else if is_capture_kind(m) {
+                                MAX_HISTORY as i32 * 4 + mvv_lva(board, m)
+                            } else {
+                                tables.quiet_score(board, self.previous_move, m)
+                            };
+                            (m, score)
+                        });
+                        self.evasions = Some(evasions.collect());
+                    }
+                    return self.evasions.as_mut().and_then(pick_best);
+                },
+                Stage::Done => return None,
+            }
+        }
     }
-    // SEE < 0
-    else if see < 0 {
-        (4, -see)
+
+    fn captures(&mut self, board: &Board) -> &mut ScoredMoves {
+        self.captures.get_or_insert_with(|| board.legal_capture_gen().into_iter().map(|m| (m, mvv_lva(board, m))).collect())
     }
-    // Quiet
-    else {
-        (5,  from_piece.value() as i16)
+
+    fn quiets(&mut self, board: &Board, tables: &OrderingTables) -> &mut ScoredMoves {
+        let previous_move = self.previous_move;
+        self.quiets.get_or_insert_with(|| board.legal_quiet_gen().into_iter().map(|m| (m, tables.quiet_score(board, previous_move, m))).collect())
     }
 }
 
-pub fn order_moves(board: &Board, moves: &mut ArrayVec<Move, MAX_MOVE_NUMBER>, last_moved_piece: Square) {
-    moves.sort_by_cached_key(|&m| move_sorting_key(board, m, last_moved_piece));
+// Moves generated by legal_capture_gen
+fn is_capture_kind(m: Move) -> bool {
+    matches!(m.infos(), MoveInfo::Capture | MoveInfo::EnPassantCapture | MoveInfo::Promotion(QUEEN) | MoveInfo::CapturePromotion(QUEEN))
+}
+
+// Most valuable victim first, then least valuable attacker
+fn mvv_lva(board: &Board, m: Move) -> i32 {
+    let victim_value = match m.infos() {
+        MoveInfo::EnPassantCapture => PAWN.value(),
+        _ => board.squares[m.to() as usize].map_or(0, |piece| piece.value()),
+    };
+    let promotion_value = match m.infos() {
+        MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) => piece.value() - PAWN.value(),
+        _ => 0,
+    };
+    let attacker = board.squares[m.from() as usize].unwrap();
+    (victim_value + promotion_value) as i32 * 8 - attacker.value() as i32 / 100
+}
+
+// Removes and returns the move with the highest score, the order of the others is not kept
+fn pick_best(moves: &mut ScoredMoves) -> Option<Move> {
+    let best_index = moves.iter().enumerate().max_by_key(|(_, (_, score))| *score).map(|(index, _)| index)?;
+    Some(moves.swap_remove(best_index).0)
 }
 
 
@@ -91,10 +413,149 @@ mod tests {
     #[test]
     fn test_see() {
         let board = Board::from_fen("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - -").expect("Invalid fen");
-        assert_eq!(static_exchange_evaluation(&board, E1, E5), 100);
+        assert_eq!(static_exchange_evaluation(&board, Move::new_base(E1, E5).with_infos(MoveInfo::Capture)), 100);
 
         let board = Board::from_fen("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - -").expect("Invalid fen");
-        assert_eq!(static_exchange_evaluation(&board, D3, E5), -225);
+        assert_eq!(static_exchange_evaluation(&board, Move::new_base(D3, E5).with_infos(MoveInfo::Capture)), -225);
+    }
+
+    fn see(fen: &str, uci_move: &str) -> i16 {
+        let board = Board::from_fen(fen).unwrap();
+        static_exchange_evaluation(&board, board.parse_uci_move(uci_move).unwrap())
+    }
+
+    #[test]
+    fn test_see_special_cases() {
+        // The knight defending e5 is pinned
+        assert_eq!(see("4k3/3n4/8/4p3/Q7/3N4/8/4K3 w - - 0 1", "d3e5"), 100);
+        // but the pinned bishop can capture on its pin line
+        assert_eq!(see("4k3/3b4/2p5/8/Q7/8/8/4K3 w - - 0 1", "a4c6"), -900);
+
+        // The king only recaptures when the piece is not defended
+        assert_eq!(see("8/8/8/3k4/4p3/8/8/4R1K1 w - - 0 1", "e1e4"), -400);
+        assert_eq!(see("8/8/8/3k4/4p3/8/2B5/4R1K1 w - - 0 1", "e1e4"), 100);
+
+        // Promotions
+        assert_eq!(see("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8q"), 1400);
+        assert_eq!(see("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), -100);
+        // The pawn recapturing on the last rank promotes
+        assert_eq!(see("1R6/7k/8/8/8/8/p7/1n2K3 w - - 0 1", "b8b1"), -1075);
+
+        // En passant
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(see("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 0);
+    }
+
+    #[test]
+    fn test_see_ge() {
+        let board = Board::from_fen("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - -").unwrap();
+        let m = Move::new_base(D3, E5).with_infos(MoveInfo::Capture);
+        assert!(see_ge(&board, m, -225));
+        assert!(!see_ge(&board, m, -224));
+        assert!(!see_ge(&board, m, 0));
+
+        let board = Board::from_fen("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - -").unwrap();
+        let m = Move::new_base(E1, E5).with_infos(MoveInfo::Capture);
+        assert!(see_ge(&board, m, 100));
+        assert!(!see_ge(&board, m, 101));
+
+        // The pawn recapturing on the last rank promotes
+        let board = Board::from_fen("1R6/7k/8/8/8/8/p7/1n2K3 w - - 0 1").unwrap();
+        let m = Move::new_base(B8, B1).with_infos(MoveInfo::Capture);
+        assert!(see_ge(&board, m, -1075));
+        assert!(!see_ge(&board, m, -1074));
+        assert!(!see_ge(&board, m, -500));
+        assert!(!see_ge(&board, m, 0));
+    }
+
+    #[test]
+    fn test_history() {
+        let board = Board::new();
+        let m = board.parse_uci_move("g1f3").unwrap();
+        let mut history = HistoryTable::new();
+
+        history.update(WHITE, m, 100);
+        assert_eq!(history.get(WHITE, m), 100);
+        assert_eq!(history.get(BLACK, m), 0);
+
+        for _ in 0..1000 {
+            history.update(WHITE, m, MAX_HISTORY);
+        }
+        assert_eq!(history.get(WHITE, m), MAX_HISTORY);
+        for _ in 0..1000 {
+            history.update(WHITE, m, -MAX_HISTORY);
+        }
+        assert_eq!(history.get(WHITE, m), -MAX_HISTORY);
+    }
+
+    #[test]
+    fn test_quiet_ordering() {
+        let mut board = Board::new();
+        let previous_move = board.parse_uci_move("e2e4").unwrap();
+        board.make(previous_move);
+        let killer = board.parse_uci_move("a7a6").unwrap();
+        let countermove = board.parse_uci_move("h7h6").unwrap();
+        let good_quiet = board.parse_uci_move("g8f6").unwrap();
+        let bad_quiet = board.parse_uci_move("b8c6").unwrap();
+
+        let mut tables = OrderingTables::new();
+        tables.update_quiet_cutoff(&board, 5, Some(previous_move), good_quiet, &[bad_quiet], 4);
+        tables.update_quiet_cutoff(&board, 3, Some(previous_move), countermove, &[], 2);
+        tables.update_quiet_cutoff(&board, 1, None, killer, &[], 2);
+        assert!(tables.quiet_score(&board, Some(previous_move), good_quiet) > tables.quiet_score(&board, None, good_quiet));
+        assert!(tables.quiet_score(&board, Some(previous_move), bad_quiet) < 0);
+
+        let moves = picked_moves(MovePicker::new(&board, &tables, 1, Some(previous_move), None), &board, &tables);
+        assert_eq!(moves[..3], [killer, countermove, good_quiet]);
+        assert_eq!(moves.last(), Some(&bad_quiet));
+    }
+
+    fn picked_moves(mut picker: MovePicker, board: &Board, tables: &OrderingTables) -> Vec<Move> {
+        let mut moves = Vec::new();
+        while let Some(m) = picker.next(board, tables) {
+            moves.push(m);
+        }
+        moves
+    }
+
+    #[test]
+    fn test_mvv_lva() {
+        // Both capture the queen, the pawn is the least valuable attacker
+        let board = Board::from_fen("4k3/8/8/3q4/4P3/8/8/3QK3 w - - 0 1").unwrap();
+        let pawn_capture = board.parse_uci_move("e4d5").unwrap();
+        let queen_capture = board.parse_uci_move("d1d5").unwrap();
+        assert!(mvv_lva(&board, pawn_capture) > mvv_lva(&board, queen_capture));
+    }
+
+    #[test]
+    fn test_move_picker() {
+        let tables = OrderingTables::new();
+        // Qxd5 wins a pawn, Rxd6 loses the rook to the pawn
+        let board = Board::from_fen("4k3/2p5/3p4/3p4/8/8/3Q4/3RK3 w - - 0 1").unwrap();
+        let hash_move = board.parse_uci_move("e1f2").unwrap();
+
+        let moves = picked_moves(MovePicker::new(&board, &tables, 0, None, Some(hash_move)), &board, &tables);
+        assert_eq!(moves.len(), board.legal_move_gen().len());
+        assert!(board.legal_move_gen().iter().all(|m| moves.contains(m)));
+        assert_eq!(moves[0], hash_move);
+        assert_eq!(moves[1], board.parse_uci_move("d2d5").unwrap());
+
+        // Quiescence skips the losing captures
+        let moves = picked_moves(MovePicker::new_quiescence(&board), &board, &tables);
+        assert_eq!(moves, [board.parse_uci_move("d2d5").unwrap()]);
+
+        // A hash move from another position is not played
+        let illegal_move = Move::new_base(A1, A8);
+        let moves = picked_moves(MovePicker::new(&board, &tables, 0, None, Some(illegal_move)), &board, &tables);
+        assert!(!moves.contains(&illegal_move));
+        assert_eq!(moves.len(), board.legal_move_gen().len());
+
+        // In check every evasion is yielded, captures of the checker first
+        let board = Board::from_fen("4k3/8/8/8/8/8/3q4/3RK3 w - - 0 1").unwrap();
+        let moves = picked_moves(MovePicker::new(&board, &tables, 0, None, None), &board, &tables);
+        assert_eq!(moves.len(), board.legal_move_gen().len());
+        assert_eq!(moves[0], board.parse_uci_move("d1d2").unwrap());
+        assert_eq!(picked_moves(MovePicker::new_quiescence(&board), &board, &tables).len(), moves.len());
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,357 @@
+// Efficiently updatable neural network evaluation
+//
+// The network is (768 -> HIDDEN_SIZE) x 2 -> 1: the accumulator of the side to move and the one of
+// the other side go through a clipped ReLU and are concatenated before the output layer
+// Each perspective has 768 inputs, one per (color, piece, square) with the color relative to the
+// perspective and the squares flipped vertically for black
+//
+// Network file format, every value being a little endian i16:
+// - feature weights: 768 rows of HIDDEN_SIZE values, the row of a feature being
+//   relative_color * 384 + piece * 64 + square with relative_color 0 for our pieces, pieces
+//   ordered pawn, knight, bishop, rook, queen, king and squares from A1 to H8
+// - feature biases: HIDDEN_SIZE values
+// - output weights: 2 * HIDDEN_SIZE values, the side to move accumulator first
+// - output bias: 1 value
+// Feature weights and biases are quantized by QA, output weights by QB and the output bias by QA * QB
+
+use std::{array, fmt::{self, Debug, Display}, fs, path::Path, sync::Arc};
+
+use crate::{board::*, evaluation::Evaluator, search::TB_WIN_BOUND};
+
+pub const INPUT_SIZE: usize = 768;
+pub const HIDDEN_SIZE: usize = 256;
+pub const NETWORK_FILE_SIZE: usize = 2 * (INPUT_SIZE * HIDDEN_SIZE + HIDDEN_SIZE + 2 * HIDDEN_SIZE + 1);
+
+const QA: i32 = 255;
+const QB: i32 = 64;
+// Converts the network output to centipawns
+const SCALE: i64 = 400;
+
+// Row of the feature weights for each piece, indexed by piece
+static FEATURE_PIECE_INDEX: [usize; 6] = [4, 3, 2, 1, 0, 5];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkError {
+    Io(String),
+    InvalidSize { expected: usize, found: usize },
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Io(err) => write!(f, "can't read network file: {err}"),
+            NetworkError::InvalidSize { expected, found } => write!(f, "network file should be {expected} bytes, found {found}"),
+        }
+    }
+}
+
+pub struct Network {
+    feature_weights: Box<[[i16; HIDDEN_SIZE]]>,
+    feature_bias: [i16; HIDDEN_SIZE],
+    output_weights: [i16; 2 * HIDDEN_SIZE],
+    output_bias: i16,
+}
+
+// The weights are too large to be printed
+impl Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Network({INPUT_SIZE} -> {HIDDEN_SIZE})")
+    }
+}
+
+impl Network {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, NetworkError> {
+        let bytes = fs::read(path).map_err(|err| NetworkError::Io(err.to_string()))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NetworkError> {
+        if bytes.len() != NETWORK_FILE_SIZE {
+            return Err(NetworkError::InvalidSize { expected: NETWORK_FILE_SIZE, found: bytes.len() });
+        }
+
+        // The size has been checked so there are always enough values
+        let mut values = bytes.chunks_exact(2).map(|value| i16::from_le_bytes([value[0], value[1]]));
+        let mut feature_weights = vec![[0; HIDDEN_SIZE]; INPUT_SIZE].into_boxed_slice();
+        for row in feature_weights.iter_mut() {
+            row.fill_with(|| values.next().unwrap());
+        }
+        let feature_bias = array::from_fn(|_| values.next().unwrap());
+        let output_weights = array::from_fn(|_| values.next().unwrap());
+        let output_bias = values.next().unwrap();
+
+        Ok(Network { feature_weights, feature_bias, output_weights, output_bias })
+    }
+
+    fn feature(&self, perspective: Color, piece: Piece, sq: Square, color: Color) -> &[i16; HIDDEN_SIZE] {
+        let (relative_color, sq) = if perspective == WHITE { (color as usize, sq) } else { (!color as usize, sq.vertical_symmetry()) };
+        &self.feature_weights[relative_color * 384 + FEATURE_PIECE_INDEX[usize::from(piece)] * 64 + sq as usize]
+    }
+}
+
+// Hidden layer before the activation, indexed by perspective
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    values: [[i16; HIDDEN_SIZE]; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct Nnue {
+    network: Arc<Network>,
+    // The last accumulator is the current one, the previous ones are restored on unmake
+    accumulators: Vec<Accumulator>,
+}
+
+impl Nnue {
+    pub fn new(network: Arc<Network>, board: &Board) -> Self {
+        let mut nnue = Nnue {
+            accumulators: vec![Accumulator { values: [network.feature_bias; 2] }],
+            network,
+        };
+        for sq in 0..64 {
+            if let Some(piece) = board.squares[sq as usize] {
+                let color = if board.pieces[WHITE].has(sq) { WHITE } else { BLACK };
+                nnue.add_piece(piece, sq, color);
+            }
+        }
+        nnue
+    }
+
+    pub fn accumulator(&self) -> &Accumulator {
+        self.accumulators.last().unwrap()
+    }
+
+    // Saves the current accumulator before making a move
+    pub fn push(&mut self) {
+        self.accumulators.push(self.accumulator().clone());
+    }
+
+    pub fn pop(&mut self) {
+        self.accumulators.pop();
+    }
+}
+
+impl Evaluator for Nnue {
+    fn add_piece(&mut self, piece: Piece, sq: Square, color: Color) {
+        let accumulator = self.accumulators.last_mut().unwrap();
+        for perspective in [WHITE, BLACK] {
+            add_feature(&mut accumulator.values[perspective as usize], self.network.feature(perspective, piece, sq, color));
+        }
+    }
+
+    fn remove_piece(&mut self, piece: Piece, sq: Square, color: Color) {
+        let accumulator = self.accumulators.last_mut().unwrap();
+        for perspective in [WHITE, BLACK] {
+            sub_feature(&mut accumulator.values[perspective as usize], self.network.feature(perspective, piece, sq, color));
+        }
+    }
+
+    fn move_piece(&mut self, piece: Piece, from: Square, to: Square, color: Color) {
+        self.remove_piece(piece, from, color);
+        self.add_piece(piece, to, color);
+    }
+
+    // Kept out of the mate and tablebase scores whatever the network outputs
+    fn score(&self, pov: Color) -> i16 {
+        let accumulator = self.accumulator();
+        let output = output(&accumulator.values[pov as usize], &accumulator.values[!pov as usize], &self.network.output_weights);
+        let score = (output as i64 + self.network.output_bias as i64) * SCALE / (QA * QB) as i64;
+        score.clamp(-TB_WIN_BOUND as i64 + 1, TB_WIN_BOUND as i64 - 1) as i16
+    }
+}
+
+// The SIMD versions are used when the CPU supports them, they give exactly the same results
+// as the scalar versions as all the arithmetic wraps in both
+
+fn add_feature(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: avx2 is supported
+        return unsafe { simd::add_feature(values, weights) };
+    }
+    scalar::add_feature(values, weights)
+}
+
+fn sub_feature(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: avx2 is supported
+        return unsafe { simd::sub_feature(values, weights) };
+    }
+    scalar::sub_feature(values, weights)
+}
+
+fn output(us: &[i16; HIDDEN_SIZE], them: &[i16; HIDDEN_SIZE], weights: &[i16; 2 * HIDDEN_SIZE]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: avx2 is supported
+        return unsafe { simd::output(us, them, weights) };
+    }
+    scalar::output(us, them, weights)
+}
+
+// Reference implementation
+mod scalar {
+    use super::*;
+
+    pub fn add_feature(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) {
+        for (value, &weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_add(weight);
+        }
+    }
+
+    pub fn sub_feature(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) {
+        for (value, &weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_sub(weight);
+        }
+    }
+
+    // Sum of the clipped ReLU of the accumulators multiplied by the output weights
+    pub fn output(us: &[i16; HIDDEN_SIZE], them: &[i16; HIDDEN_SIZE], weights: &[i16; 2 * HIDDEN_SIZE]) -> i32 {
+        us.iter().chain(them).zip(weights).fold(0i32, |sum, (&value, &weight)| {
+            sum.wrapping_add((value as i32).clamp(0, QA) * weight as i32)
+        })
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::*;
+
+    use super::*;
+
+    // Number of i16 in a 256 bits register
+    const LANES: usize = 16;
+
+    #[target_feature(enable = "avx2")]
+    pub fn add_feature(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) {
+        for (values, weights) in values.chunks_exact_mut(LANES).zip(weights.chunks_exact(LANES)) {
+            // SAFETY: both chunks are LANES i16 long, unaligned loads and stores are used
+            unsafe {
+                let sum = _mm256_add_epi16(_mm256_loadu_si256(values.as_ptr().cast()), _mm256_loadu_si256(weights.as_ptr().cast()));
+                _mm256_storeu_si256(values.as_mut_ptr().cast(), sum);
+            }
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub fn sub_feature(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) {
+        for (values, weights) in values.chunks_exact_mut(LANES).zip(weights.chunks_exact(LANES)) {
+            // SAFETY: both chunks are LANES i16 long, unaligned loads and stores are used
+            unsafe {
+                let difference = _mm256_sub_epi16(_mm256_loadu_si256(values.as_ptr().cast()), _mm256_loadu_si256(weights.as_ptr().cast()));
+                _mm256_storeu_si256(values.as_mut_ptr().cast(), difference);
+            }
+        }
+    }
+
+    // The clipped values are at most QA so a pair of products always fits in i32
+    #[target_feature(enable = "avx2")]
+    pub fn output(us: &[i16; HIDDEN_SIZE], them: &[i16; HIDDEN_SIZE], weights: &[i16; 2 * HIDDEN_SIZE]) -> i32 {
+        let zero = _mm256_setzero_si256();
+        let max = _mm256_set1_epi16(QA as i16);
+        let mut sum = zero;
+        let values = us.chunks_exact(LANES).chain(them.chunks_exact(LANES));
+        for (values, weights) in values.zip(weights.chunks_exact(LANES)) {
+            // SAFETY: both chunks are LANES i16 long, unaligned loads are used
+            let (values, weights) = unsafe { (_mm256_loadu_si256(values.as_ptr().cast()), _mm256_loadu_si256(weights.as_ptr().cast())) };
+            let clipped = _mm256_min_epi16(_mm256_max_epi16(values, zero), max);
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, weights));
+        }
+
+        let mut lanes = [0i32; 8];
+        // SAFETY: lanes is 256 bits long
+        unsafe { _mm256_storeu_si256(lanes.as_mut_ptr().cast(), sum) };
+        lanes.iter().fold(0, |total, &lane| total.wrapping_add(lane))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_network(seed: u64) -> Network {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let bytes: Vec<u8> = (0..NETWORK_FILE_SIZE / 2).flat_map(|_| rng.random_range(-64i16..64).to_le_bytes()).collect();
+        Network::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_load() {
+        assert_eq!(Network::from_bytes(&[0; 10]).unwrap_err(), NetworkError::InvalidSize { expected: NETWORK_FILE_SIZE, found: 10 });
+        assert!(matches!(Network::load("/nonexistent/network.bin"), Err(NetworkError::Io(_))));
+
+        let path = std::env::temp_dir().join("yace_test_network.bin");
+        let mut bytes = vec![0; NETWORK_FILE_SIZE];
+        bytes[NETWORK_FILE_SIZE - 2..].copy_from_slice(&(QA * QB).to_le_bytes()[..2]);
+        fs::write(&path, &bytes).unwrap();
+        let network = Network::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Only the output bias is set, worth one network unit
+        let nnue = Nnue::new(Arc::new(network), &Board::new());
+        assert_eq!(nnue.score(WHITE), SCALE as i16);
+    }
+
+    #[test]
+    fn test_incremental_accumulator() {
+        let network = Arc::new(random_network(1));
+        let mut board = Board::new();
+        board.set_network(Some(network.clone()));
+        let start = board.nnue.as_ref().unwrap().accumulator().clone();
+
+        // Castling, en passant, captures and promotions
+        let mut ext_moves = Vec::new();
+        for uci_move in ["e2e4", "d7d5", "e4e5", "f7f5", "e5f6", "g8h6", "f6g7", "e8f7", "g7h8q", "h6g4", "g1f3", "g4f2", "f1e2", "f2d1", "e1g1"] {
+            ext_moves.push(board.make(board.parse_uci_move(uci_move).unwrap()));
+            let refreshed = Nnue::new(network.clone(), &board);
+            assert_eq!(board.nnue.as_ref().unwrap().accumulator(), refreshed.accumulator(), "{}", board.to_fen());
+        }
+
+        while let Some(ext_move) = ext_moves.pop() {
+            board.unmake(ext_move);
+        }
+        assert_eq!(board.nnue.as_ref().unwrap().accumulator(), &start);
+        assert_eq!(board.nnue.as_ref().unwrap().accumulators.len(), 1);
+    }
+
+    #[test]
+    fn test_symmetry() {
+        let network = Arc::new(random_network(2));
+        let white = Nnue::new(network.clone(), &Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap());
+        let black = Nnue::new(network, &Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap());
+        assert_eq!(white.score(BLACK), black.score(WHITE));
+        assert_eq!(white.score(WHITE), black.score(BLACK));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_simd() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        // Extreme values to check that both versions wrap and clip the same way
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..100 {
+            let us: [i16; HIDDEN_SIZE] = array::from_fn(|_| rng.random());
+            let them: [i16; HIDDEN_SIZE] = array::from_fn(|_| rng.random());
+            let weights: [i16; 2 * HIDDEN_SIZE] = array::from_fn(|_| rng.random());
+            // SAFETY: avx2 is supported
+            assert_eq!(unsafe { simd::output(&us, &them, &weights) }, scalar::output(&us, &them, &weights));
+
+            let (mut scalar_values, mut simd_values) = (us, us);
+            scalar::add_feature(&mut scalar_values, &them);
+            // SAFETY: avx2 is supported
+            unsafe { simd::add_feature(&mut simd_values, &them) };
+            assert_eq!(scalar_values, simd_values);
+
+            scalar::sub_feature(&mut scalar_values, &us);
+            // SAFETY: avx2 is supported
+            unsafe { simd::sub_feature(&mut simd_values, &us) };
+            assert_eq!(scalar_values, simd_values);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use bit_iter::BitIter;
+
+use crate::{board::*, evaluation::{Term, Trace}};
+
+// Number of entries of the pawn hash table, a power of two
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+// Penalties and bonuses are (middlegame, endgame)
+const DOUBLED_PENALTY: (i16, i16) = (-10, -25);
+const ISOLATED_PENALTY: (i16, i16) = (-10, -15);
+// Can't be defended by another pawn and its stop square is controlled by an enemy pawn
+const BACKWARD_PENALTY: (i16, i16) = (-8, -12);
+
+// Indexed by the rank relative to the pawn owner
+static CONNECTED_BONUS: [i16; 8] = [0, 0, 5, 8, 15, 25, 45, 0];
+static PASSED_BONUS_MIDDLE: [i16; 8] = [0, 5, 10, 15, 25, 40, 60, 0];
+static PASSED_BONUS_END: [i16; 8] = [0, 10, 15, 25, 45, 75, 110, 0];
+
+// Pawn structure score from white point of view, only depends on the pawns so it can be cached
+#[derive(Debug, Clone, Copy)]
+pub struct PawnEntry {
+    pawn_hash: ZobristHash,
+    middle_game: i16,
+    end_game: i16,
+    // Passed pawns of each color, their bonus is reduced later when they are blocked by a piece
+    passed: [Bitboard; 2],
+}
+
+pub struct PawnHashTable {
+    entries: Box<[Option<PawnEntry>]>,
+}
+
+impl PawnHashTable {
+    pub fn new() -> Self {
+        PawnHashTable { entries: vec![None; PAWN_TABLE_SIZE].into_boxed_slice() }
+    }
+
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let index = board.pawn_hash as usize & (PAWN_TABLE_SIZE - 1);
+        if let Some(entry) = self.entries[index] && entry.pawn_hash == board.pawn_hash {
+            return entry;
+        }
+
+        let entry = evaluate_pawns(board, &mut ());
+        self.entries[index] = Some(entry);
+        entry
+    }
+}
+
+// Pawn structure as (middlegame, endgame) from white point of view
+// The cache is bypassed when tracing so that every pawn term is reported
+pub fn pawn_structure<T: Trace>(board: &Board, pawn_table: &mut PawnHashTable, trace: &mut T) -> (i16, i16) {
+    let entry = if T::ENABLED { evaluate_pawns(board, trace) } else { pawn_table.probe(board) };
+    let (white_middle, white_end) = blocked_passed_penalty::<WHITE>(board, entry.passed[WHITE as usize], trace);
+    let (black_middle, black_end) = blocked_passed_penalty::<BLACK>(board, entry.passed[BLACK as usize], trace);
+    (entry.middle_game + white_middle - black_middle, entry.end_game + white_end - black_end)
+}
+
+// A passed pawn with a piece right in front of it only gets half of its bonus
+fn blocked_passed_penalty<const COLOR: bool>(board: &Board, passed: Bitboard, trace: &mut impl Trace) -> (i16, i16) {
+    // Squares right behind a piece from the point of view of COLOR
+    let blocked = if COLOR == WHITE { passed & (board.occupancy() >> 8) } else { passed & (board.occupancy() << 8) };
+    BitIter::from(blocked).fold((0, 0), |(middle_game, end_game), sq| {
+        let rank = relative_rank::<COLOR>(sq as Square);
+        trace.term(Term::BlockedPassedPawn, COLOR, Some(sq as Square), -PASSED_BONUS_MIDDLE[rank] / 2, -PASSED_BONUS_END[rank] / 2);
+        (middle_game - PASSED_BONUS_MIDDLE[rank] / 2, end_game - PASSED_BONUS_END[rank] / 2)
+    })
+}
+
+fn evaluate_pawns(board: &Board, trace: &mut impl Trace) -> PawnEntry {
+    let white_pawns = board.bitboards[PAWN] & board.pieces[WHITE];
+    let black_pawns = board.bitboards[PAWN] & board.pieces[BLACK];
+    let (white_middle, white_end, white_passed) = evaluate_color::<WHITE>(white_pawns, black_pawns, trace);
+    let (black_middle, black_end, black_passed) = evaluate_color::<BLACK>(black_pawns, white_pawns, trace);
+
+    PawnEntry {
+        pawn_hash: board.pawn_hash,
+        middle_game: white_middle - black_middle,
+        end_game: white_end - black_end,
+        passed: [white_passed, black_passed],
+    }
+}
+
+// Score of the pawns of COLOR and the bitboard of its passed pawns
+fn evaluate_color<const COLOR: bool>(own_pawns: Bitboard, enemy_pawns: Bitboard, trace: &mut impl Trace) -> (i16, i16, Bitboard) {
+    let own_attacks = pawn_attacks(own_pawns, COLOR);
+    let enemy_attacks = pawn_attacks(enemy_pawns, !COLOR);
+
+    let (mut middle_game, mut end_game) = (0, 0);
+    let mut passed = EMPTY;
+    for sq in BitIter::from(own_pawns) {
+        let sq = sq as Square;
+        let bitboard = sq.as_bitboard();
+        let adjacent_files = adjacent_files(sq.file());
+        let front = front_span::<COLOR>(bitboard);
+
+        let doubled = own_pawns & front != EMPTY;
+        let isolated = own_pawns & adjacent_files == EMPTY;
+        let supported = own_attacks.has(sq);
+        let phalanx = own_pawns & adjacent_files & RANKS[sq.rank() as usize] != EMPTY;
+        // No pawn on the adjacent files is level or behind, so it can never be defended by a pawn
+        let backward = !isolated
+            && own_pawns & adjacent_files & !front_span::<COLOR>(RANKS[sq.rank() as usize]) == EMPTY
+            && enemy_attacks.has(sq.forward::<COLOR>());
+
+        if doubled {
+            middle_game += DOUBLED_PENALTY.0;
+            end_game += DOUBLED_PENALTY.1;
+            trace.term(Term::DoubledPawn, COLOR, Some(sq), DOUBLED_PENALTY.0, DOUBLED_PENALTY.1);
+        }
+        if isolated {
+            middle_game += ISOLATED_PENALTY.0;
+            end_game += ISOLATED_PENALTY.1;
+            trace.term(Term::IsolatedPawn, COLOR, Some(sq), ISOLATED_PENALTY.0, ISOLATED_PENALTY.1);
+        } else if backward {
+            middle_game += BACKWARD_PENALTY.0;
+            end_game += BACKWARD_PENALTY.1;
+            trace.term(Term::BackwardPawn, COLOR, Some(sq), BACKWARD_PENALTY.0, BACKWARD_PENALTY.1);
+        }
+
+        let rank = relative_rank::<COLOR>(sq);
+        if supported || phalanx {
+            middle_game += CONNECTED_BONUS[rank];
+            end_game += CONNECTED_BONUS[rank];
+            trace.term(Term::ConnectedPawn, COLOR, Some(sq), CONNECTED_BONUS[rank], CONNECTED_BONUS[rank]);
+        }
+
+        // Only the frontmost of doubled pawns can be passed
+        let passed_span = front | adjacent_span(front);
+        if !doubled && enemy_pawns & passed_span == EMPTY {
+            passed = passed.set(sq);
+            middle_game += PASSED_BONUS_MIDDLE[rank];
+            end_game += PASSED_BONUS_END[rank];
+            trace.term(Term::PassedPawn, COLOR, Some(sq), PASSED_BONUS_MIDDLE[rank], PASSED_BONUS_END[rank]);
+        }
+    }
+
+    (middle_game, end_game, passed)
+}
+
+pub fn pawn_attacks(pawns: Bitboard, color: Color) -> Bitboard {
+    if color == WHITE {
+        pawns.forward_left::<WHITE>() | pawns.forward_right::<WHITE>()
+    } else {
+        pawns.forward_left::<BLACK>() | pawns.forward_right::<BLACK>()
+    }
+}
+
+fn relative_rank<const COLOR: bool>(sq: Square) -> usize {
+    if COLOR == WHITE { sq.rank() as usize } else { 7 - sq.rank() as usize }
+}
+
+fn adjacent_files(file: i8) -> Bitboard {
+    adjacent_span(FILES[file as usize])
+}
+
+fn adjacent_span(bitboard: Bitboard) -> Bitboard {
+    ((bitboard & !FILEA) >> 1) | ((bitboard & !FILEH) << 1)
+}
+
+// Every square in front of the bitboard squares, from the point of view of COLOR
+fn front_span<const COLOR: bool>(bitboard: Bitboard) -> Bitboard {
+    let mut span = bitboard.forward::<COLOR>();
+    if COLOR == WHITE {
+        span |= span << 8;
+        span |= span << 16;
+        span |= span << 32;
+    } else {
+        span |= span >> 8;
+        span |= span >> 16;
+        span |= span >> 32;
+    }
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pawns(fen: &str, color: Color) -> Bitboard {
+        let board = Board::from_fen(fen).unwrap();
+        board.bitboards[PAWN] & board.pieces[color]
+    }
+
+    #[test]
+    fn test_pawn_terms() {
+        // c2 is doubled and isolated, c3 is isolated and passed
+        let own_pawns = pawns("4k3/8/8/8/8/2P5/2P5/4K3 w - - 0 1", WHITE);
+        let passed = square_from_str("c3").unwrap().as_bitboard();
+        assert_eq!(evaluate_color::<WHITE>(own_pawns, EMPTY, &mut ()), (-20, -40, passed));
+
+        // d3 is backward only when an enemy pawn controls d4, e4 is connected and passed
+        let own_pawns = pawns("4k3/8/8/8/4P3/3P4/8/4K3 w - - 0 1", WHITE);
+        let (middle_game, end_game, passed) = evaluate_color::<WHITE>(own_pawns, pawns("4k3/8/8/2p5/8/8/8/4K3 w - - 0 1", BLACK), &mut ());
+        let (free_middle_game, free_end_game, free_passed) = evaluate_color::<WHITE>(own_pawns, pawns("4k3/8/2p5/8/8/8/8/4K3 w - - 0 1", BLACK), &mut ());
+        assert_eq!((free_middle_game - middle_game, free_end_game - end_game), (-BACKWARD_PENALTY.0, -BACKWARD_PENALTY.1));
+        assert_eq!(passed, square_from_str("e4").unwrap().as_bitboard());
+        assert_eq!(passed, free_passed);
+
+        // The same structure for black is the mirrored score
+        let own_pawns = pawns("4k3/2p5/2p5/8/8/8/8/4K3 w - - 0 1", BLACK);
+        let passed = square_from_str("c6").unwrap().as_bitboard();
+        assert_eq!(evaluate_color::<BLACK>(own_pawns, EMPTY, &mut ()), (-20, -40, passed));
+    }
+
+    #[test]
+    fn test_blocked_passed_pawn() {
+        let mut pawn_table = PawnHashTable::new();
+        let free = Board::from_fen("8/8/2k5/4P3/8/8/8/4K3 w - - 0 1").unwrap();
+        let blocked = Board::from_fen("8/8/4k3/4P3/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(free.pawn_hash, blocked.pawn_hash);
+        assert_eq!(pawn_structure(&free, &mut pawn_table, &mut ()), (15, 30));
+        assert_eq!(pawn_structure(&blocked, &mut pawn_table, &mut ()), (3, 8));
+
+        let blocked = Board::from_fen("4k3/8/8/8/4p3/4K3/8/8 w - - 0 1").unwrap();
+        assert_eq!(pawn_structure(&blocked, &mut pawn_table, &mut ()), (-3, -8));
+    }
+
+    #[test]
+    fn test_pawn_table() {
+        let mut pawn_table = PawnHashTable::new();
+        let board = Board::new();
+        assert_eq!(pawn_structure(&board, &mut pawn_table, &mut ()), (0, 0));
+        assert!(pawn_table.entries.iter().flatten().any(|entry| entry.pawn_hash == board.pawn_hash));
+
+        let board = Board::from_fen("rnbqkbnr/pp3ppp/8/2pp4/8/8/PPP2PPP/RNBQKBNR w KQkq - 0 5").unwrap();
+        let entry = pawn_table.probe(&board);
+        assert_eq!(pawn_table.probe(&board).middle_game, entry.middle_game);
+        // Only the black pawns on c5 and d5 are connected beyond the second rank
+        assert_eq!((entry.middle_game, entry.end_game), (-2 * CONNECTED_BONUS[3], -2 * CONNECTED_BONUS[3]));
+    }
+}
@@ -1,64 +1,733 @@
-use std::i16;
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc, LazyLock};
 
-use crate::{board::*, move_ordering::order_moves};
+use arrayvec::ArrayVec;
 
-struct PVNode {
-    pv_move: Move,
-    evaluation: i16,
+use crate::{board::*, evaluation::evaluate, move_ordering::{MovePicker, OrderingTables}, pawn_structure::PawnHashTable, syzygy::{Tablebases, Wdl}, time_manager::TimeManager, transposition::{Bound, TranspositionTable}};
+
+pub const MAX_DEPTH: u8 = 64;
+// Maximum distance from the root, the search never goes deeper than this
+pub const MAX_PLY: usize = 128;
+
+// Scores above MATE_BOUND are mate scores, MATE_SCORE - MATE_BOUND being the maximum distance to mate
+pub const MATE_SCORE: i16 = 30000;
+pub const MATE_BOUND: i16 = MATE_SCORE - MAX_PLY as i16;
+// Tablebase wins are scored below the mates, minus their distance from the root
+pub const TB_WIN_SCORE: i16 = MATE_BOUND - 1;
+pub const TB_WIN_BOUND: i16 = TB_WIN_SCORE - MAX_PLY as i16;
+
+// Every score is strictly within ]-INFINITY, INFINITY[ so negating never overflows
+const INFINITY: i16 = MATE_SCORE + 1;
+const DRAW_SCORE: i16 = 0;
+
+// Half width of the first aspiration window, doubled on each fail low or fail high
+const ASPIRATION_WINDOW: i16 = 25;
+// Shallower iterations are too unstable for a narrow window to pay off
+const ASPIRATION_MIN_DEPTH: u8 = 4;
+
+// Null move pruning is only tried from this depth, and verified by a reduced search from NULL_MOVE_VERIFICATION_DEPTH
+const NULL_MOVE_MIN_DEPTH: u8 = 3;
+const NULL_MOVE_VERIFICATION_DEPTH: u8 = 10;
+
+// Late quiet moves are reduced from LMR_MIN_DEPTH, after the first LMR_MIN_MOVE_INDEX moves
+const LMR_MIN_DEPTH: u8 = 3;
+const LMR_MIN_MOVE_INDEX: usize = 3;
+// A history score of HISTORY_REDUCTION_DIVISOR reduces one ply less, a negative one reduces one ply more
+const HISTORY_REDUCTION_DIVISOR: i16 = 8192;
+
+// At depth d only the first LATE_MOVE_PRUNING_COUNTS[d] quiet moves are searched in non PV nodes
+const LATE_MOVE_PRUNING_COUNTS: [usize; 4] = [0, 5, 8, 13];
+
+// Reductions grow with the logarithm of both the depth and the move index
+static LMR_TABLE: LazyLock<[[u8; 64]; MAX_DEPTH as usize + 1]> = LazyLock::new(|| {
+    let mut table = [[0; 64]; MAX_DEPTH as usize + 1];
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (move_index, reduction) in row.iter_mut().enumerate().skip(1) {
+            *reduction = (0.75 + (depth as f64).ln() * (move_index as f64).ln() / 2.25) as u8;
+        }
+    }
+    table
+});
+
+// Captures that can't bring the score back above alpha with this margin are skipped in quiescence
+const DELTA_MARGIN: i16 = 200;
+
+// The clock and the stop flag are only checked every CHECK_INTERVAL nodes
+const CHECK_INTERVAL: u64 = 2048;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    // None only if there is no legal move in the root position
+    pub best_move: Option<Move>,
+    pub ponder_move: Option<Move>,
+    pub score: i16,
+    pub depth: u8,
+    // Maximum ply reached during the search
+    pub seldepth: u8,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    // Permille of the transposition table in use
+    pub hashfull: usize,
+    pub tbhits: u64,
 }
 
+type InfoCallback<'a> = Box<dyn FnMut(&SearchResult) + 'a>;
+
 pub struct Searcher<'a> {
-    principal_variation: Vec<PVNode>,
+    // Principal variation of the last completed iteration, searched first in the next one
+    principal_variation: Vec<Move>,
+    // Triangular table, pv_table[ply] holds the best line found from ply, up to pv_length[ply]
+    pv_table: Box<[[Move; MAX_PLY]; MAX_PLY]>,
+    pv_length: [usize; MAX_PLY],
+    follow_pv: bool,
+    // Set at the ply where a null move has been made, two null moves in a row would prove nothing
+    null_move: [bool; MAX_PLY],
+    // Null moves are not allowed while verifying a null move cutoff
+    null_move_verification: bool,
+
+    ordering: OrderingTables,
+    // Move made at each ply of the current line, None for a null move
+    move_stack: [Option<Move>; MAX_PLY],
+    pawn_table: PawnHashTable,
+
     board: &'a mut Board,
+    tt: &'a mut TranspositionTable,
+    info_callback: Option<InfoCallback<'a>>,
+    tablebases: Option<&'a Tablebases>,
+    // Positions with at most this many pieces are probed in the search, 0 when the root moves
+    // have already been restricted with the tablebases
+    tb_probe_limit: u32,
+    // Only these moves are searched at the root when set
+    root_moves: Option<Vec<Move>>,
+
+    nodes: u64,
+    tb_hits: u64,
+    seldepth: u8,
+    stop: Arc<AtomicBool>,
+    time_manager: TimeManager,
+    aborted: bool,
 }
 
 impl<'a> Searcher<'a> {
-    pub fn new(board: &'a mut Board) -> Self {
-        Searcher { principal_variation: Vec::with_capacity(32), board, }
+    pub fn new(board: &'a mut Board, tt: &'a mut TranspositionTable, stop: Arc<AtomicBool>) -> Self {
+        Searcher {
+            principal_variation: Vec::with_capacity(MAX_DEPTH as usize),
+            pv_table: Box::new([[Move::new(); MAX_PLY]; MAX_PLY]),
+            pv_length: [0; MAX_PLY],
+            follow_pv: false,
+            null_move: [false; MAX_PLY],
+            null_move_verification: false,
+            ordering: OrderingTables::new(),
+            move_stack: [None; MAX_PLY],
+            pawn_table: PawnHashTable::new(),
+            board,
+            tt,
+            info_callback: None,
+            tablebases: None,
+            tb_probe_limit: 0,
+            root_moves: None,
+            nodes: 0,
+            tb_hits: 0,
+            seldepth: 0,
+            stop,
+            time_manager: TimeManager::infinite(),
+            aborted: false,
+        }
+    }
+
+    pub fn with_time_manager(mut self, time_manager: TimeManager) -> Self {
+        self.time_manager = time_manager;
+        self
+    }
+
+    // Called with the result of each completed iteration
+    pub fn with_info_callback(mut self, callback: impl FnMut(&SearchResult) + 'a) -> Self {
+        self.info_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn with_tablebases(mut self, tablebases: Option<&'a Tablebases>) -> Self {
+        self.tablebases = tablebases;
+        self
+    }
+
+    // Iterative deepening from depth 1 up to max_depth, until the search is stopped or runs out of time
+    // The result is the one of the last completed iteration
+    pub fn search(&mut self, max_depth: u8) -> SearchResult {
+        self.aborted = false;
+        self.principal_variation.clear();
+
+        // When the root is in the tablebases only the moves keeping its outcome are searched,
+        // the search then only has to find the way to convert it
+        self.root_moves = self.tablebases.and_then(|tablebases| tablebases.root_moves(self.board));
+        self.tb_probe_limit = match (self.tablebases, &self.root_moves) {
+            (Some(tablebases), None) => tablebases.max_pieces() as u32,
+            _ => 0,
+        };
+
+        let mut result = SearchResult::default();
+
+        for depth in 1..=max_depth.min(MAX_DEPTH) {
+            let score = self.aspiration_search(depth, result.score);
+
+            if self.aborted {
+                // A partial first iteration is still better than no move at all
+                if result.best_move.is_none() && self.pv_length[0] > 0 {
+                    result.best_move = Some(self.pv_table[0][0]);
+                }
+                break;
+            }
+
+            self.principal_variation.clear();
+            self.principal_variation.extend_from_slice(&self.pv_table[0][..self.pv_length[0]]);
+
+            result = SearchResult {
+                best_move: self.principal_variation.first().copied(),
+                ponder_move: self.principal_variation.get(1).copied(),
+                score,
+                depth,
+                seldepth: self.seldepth,
+                nodes: self.nodes,
+                pv: self.principal_variation.clone(),
+                hashfull: self.tt.hashfull(),
+                tbhits: self.tb_hits,
+            };
+
+            if let Some(callback) = self.info_callback.as_mut() {
+                callback(&result);
+            }
+
+            if result.best_move.is_none() || !self.time_manager.should_start_iteration() {
+                break;
+            }
+        }
+
+        result.nodes = self.nodes;
+        result.tbhits = self.tb_hits;
+        result
+    }
+
+    // Searches the root with a narrow window around the score of the previous iteration
+    // and widens it on the failing side until the score falls inside
+    fn aspiration_search(&mut self, depth: u8, previous_score: i16) -> i16 {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = if depth >= ASPIRATION_MIN_DEPTH && previous_score.abs() < MATE_BOUND {
+            (previous_score - delta, previous_score + delta)
+        } else {
+            (-INFINITY, INFINITY)
+        };
+
+        loop {
+            self.follow_pv = true;
+            let score = self.alphabeta(alpha, beta, depth, 0);
+
+            if self.aborted {
+                return score;
+            }
+
+            if score <= alpha {
+                alpha = score.saturating_sub(delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = score.saturating_add(delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta = delta.saturating_mul(2);
+        }
     }
 
-    pub fn search(&mut self, depth: u8) -> i16 {
-        self.alphabeta(i16::MIN, i16::MAX, depth)
+    fn should_stop(&mut self) -> bool {
+        if !self.aborted && self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            self.aborted = self.stop.load(Ordering::Relaxed)
+                || self.time_manager.hard_limit_reached();
+        }
+        self.aborted
     }
 
-    fn alphabeta(&mut self, mut alpha: i16, beta: i16, depthleft: u8) -> i16 {
+    // Move of the previous principal variation to search first if we are still on it
+    fn pv_move(&mut self, ply: u8) -> Option<Move> {
+        if self.follow_pv && (ply as usize) < self.principal_variation.len() {
+            Some(self.principal_variation[ply as usize])
+        } else {
+            self.follow_pv = false;
+            None
+        }
+    }
+
+    fn update_pv(&mut self, ply: u8, best_move: Move) {
+        let ply = ply as usize;
+        let child_length = self.pv_length[ply+1].max(ply+1);
+        self.pv_table[ply][ply] = best_move;
+        let (current, child) = self.pv_table.split_at_mut(ply+1);
+        current[ply][ply+1..child_length].copy_from_slice(&child[0][ply+1..child_length]);
+        self.pv_length[ply] = child_length;
+    }
+
+    fn alphabeta(&mut self, mut alpha: i16, mut beta: i16, depthleft: u8, ply: u8) -> i16 {
+        self.nodes += 1;
+        self.pv_length[ply as usize] = ply as usize;
+        self.seldepth = self.seldepth.max(ply);
+
+        if self.should_stop() {
+            return 0;
+        }
+
+        let is_root = ply == 0;
+        // Only PV nodes are searched with an open window, every other node is a null window scout
+        let pv_node = beta > alpha + 1;
+
+        if !is_root {
+            if self.board.is_repetition() || self.board.is_fifty_move_draw() || self.board.is_insufficient_material() {
+                return DRAW_SCORE;
+            }
+
+            // Mate distance pruning, a shorter mate has already been found
+            alpha = alpha.max(-MATE_SCORE + ply as i16);
+            beta = beta.min(MATE_SCORE - ply as i16 - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        if ply as usize >= MAX_PLY - 1 {
+            return evaluate(self.board, &mut self.pawn_table);
+        }
+
         if depthleft == 0 {
-            return self.board.evaluation.score(self.board.to_move);
+            return self.quiescence(alpha, beta, ply);
+        }
+
+        let hash = self.board.zobrist_hash;
+        let tt_entry = self.tt.probe(hash);
+        // Cutoffs would cut the principal variation short in PV nodes
+        if let Some(entry) = tt_entry && entry.depth >= depthleft && !pv_node {
+            let score = entry.score(ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => (),
+            }
+        }
+
+        // Bounds from the tablebases on the score of a PV node, when they do not cut it
+        let mut tb_min_score = -INFINITY;
+        let mut tb_max_score = INFINITY;
+
+        // Exact outcome right after a capture or a pawn move, when the fifty-move counter is reset
+        if !is_root && self.board.halfmove_clock() == 0 && self.board.castling_rights() == 0
+        && self.board.occupancy().count_ones() <= self.tb_probe_limit
+        && let Some(tablebases) = self.tablebases
+        && let Some(wdl) = tablebases.probe_wdl(self.board) {
+            self.tb_hits += 1;
+            // Cursed wins and blessed losses are draws with the fifty-move rule
+            let (score, bound) = match wdl {
+                Wdl::Win => (TB_WIN_SCORE - ply as i16, Bound::Lower),
+                Wdl::Loss => (-TB_WIN_SCORE + ply as i16, Bound::Upper),
+                _ => (DRAW_SCORE, Bound::Exact),
+            };
+            if bound == Bound::Exact || (bound == Bound::Lower && score >= beta) || (bound == Bound::Upper && score <= alpha) {
+                self.tt.store(hash, None, score, (depthleft + 6).min(MAX_DEPTH), bound, ply);
+                return score;
+            }
+            // A won node is still searched for the shortest conversion, a lost one for the longest resistance
+            if pv_node {
+                if bound == Bound::Lower {
+                    tb_min_score = score;
+                    alpha = alpha.max(score);
+                } else {
+                    tb_max_score = score;
+                }
+            }
+        }
+
+        let in_check = self.board.in_check();
+
+        if !pv_node && !in_check && depthleft >= NULL_MOVE_MIN_DEPTH && beta.abs() < MATE_BOUND
+        && let Some(score) = self.null_move_pruning(beta, depthleft, ply) {
+            return score;
         }
 
-        let mut possible_moves = self.board.legal_move_gen();
-        order_moves(self.board, &mut possible_moves, A1);
-        let mut max_score = i16::MIN;
+        if self.aborted {
+            return 0;
+        }
+
+        let original_alpha = alpha;
+        let first_move = self.pv_move(ply).or(tt_entry.and_then(|entry| entry.best_move));
+        let previous_move = self.previous_move(ply);
+        let mut move_picker = MovePicker::new(self.board, &self.ordering, ply, previous_move, first_move);
+        let mut move_count = 0;
+        let mut max_score = tb_min_score;
+        let mut best_move = None;
+        let mut searched_quiets: ArrayVec<Move, MAX_MOVE_NUMBER> = ArrayVec::new();
+        // Once a side is left with its king and pawns the quiet moves are the whole game, a short
+        // mate or a pawn race would be missed if they were reduced or pruned
+        let late_quiets_cut = self.board.has_non_pawn_material(WHITE) && self.board.has_non_pawn_material(BLACK);
 
-        for possible_move in possible_moves {
+        while let Some(possible_move) = move_picker.next(self.board, &self.ordering) {
+            if is_root && let Some(root_moves) = &self.root_moves && !root_moves.contains(&possible_move) {
+                continue;
+            }
+            let move_index = move_count;
+            move_count += 1;
+            let quiet = possible_move.is_quiet();
 
             // Make -> recursive eval -> unmake
+            self.move_stack[ply as usize] = Some(possible_move);
             let ext_move = self.board.make(possible_move);
-            let score = -self.alphabeta(-beta, -alpha, depthleft-1);
+            let gives_check = self.board.in_check();
+
+            // Late move pruning, late quiet moves at shallow depth are very unlikely to raise alpha
+            // unless they give check
+            if late_quiets_cut && !pv_node && !in_check && quiet && !gives_check && max_score > -MATE_BOUND
+            && (depthleft as usize) < LATE_MOVE_PRUNING_COUNTS.len() && searched_quiets.len() >= LATE_MOVE_PRUNING_COUNTS[depthleft as usize] {
+                self.board.unmake(ext_move);
+                continue;
+            }
+
+            // The first move is expected to be the best one, the others only have to be proven worse
+            // with a null window, and are searched again with the full window if they are not
+            let score = if move_index == 0 {
+                -self.alphabeta(-beta, -alpha, depthleft-1, ply+1)
+            } else {
+                let reduction = if late_quiets_cut && depthleft >= LMR_MIN_DEPTH && move_index >= LMR_MIN_MOVE_INDEX && quiet && !in_check && !gives_check {
+                    self.late_move_reduction(possible_move, depthleft, move_index, pv_node)
+                } else {
+                    0
+                };
+
+                let mut score = -self.alphabeta(-alpha-1, -alpha, depthleft-1-reduction, ply+1);
+                if score > alpha && reduction > 0 && !self.aborted {
+                    score = -self.alphabeta(-alpha-1, -alpha, depthleft-1, ply+1);
+                }
+                if score > alpha && score < beta && !self.aborted {
+                    score = -self.alphabeta(-beta, -alpha, depthleft-1, ply+1);
+                }
+                score
+            };
             self.board.unmake(ext_move);
 
+            // Only the first move can be on the previous principal variation
+            self.follow_pv = false;
+
+            if self.aborted {
+                return 0;
+            }
+
             // update alpha and best max score
             if score > max_score {
-                max_score = alpha;
+                max_score = score;
+                best_move = Some(possible_move);
                 if score > alpha {
                     alpha = score;
+                    self.update_pv(ply, possible_move);
                 }
             }
 
             // beta cutoff
             if score >= beta {
+                if quiet {
+                    self.ordering.update_quiet_cutoff(self.board, ply, previous_move, possible_move, &searched_quiets, depthleft);
+                }
+                self.tt.store(hash, best_move, score, depthleft, Bound::Lower, ply);
                 return score
             }
 
+            if quiet {
+                searched_quiets.push(possible_move);
+            }
+        }
+
+        if move_count == 0 {
+            return if in_check {-MATE_SCORE + ply as i16} else {DRAW_SCORE};
         }
 
+        let max_score = max_score.min(tb_max_score);
+        let bound = if alpha > original_alpha {Bound::Exact} else {Bound::Upper};
+        self.tt.store(hash, best_move, max_score, depthleft, bound, ply);
+
         max_score
     }
 
+    // Must be called with the move made on the board
+    fn late_move_reduction(&self, m: Move, depthleft: u8, move_index: usize, pv_node: bool) -> u8 {
+        let mut reduction = LMR_TABLE[depthleft as usize][move_index.min(63)] as i16;
+        if pv_node {
+            reduction -= 1;
+        }
+        reduction -= self.ordering.history.get(!self.board.to_move, m) / HISTORY_REDUCTION_DIVISOR;
+
+        // The reduced search is at least one ply deep
+        reduction.clamp(0, depthleft as i16 - 2) as u8
+    }
+
+    // Move that led to the node at ply, unknown at the root
+    fn previous_move(&self, ply: u8) -> Option<Move> {
+        ply.checked_sub(1).and_then(|previous_ply| self.move_stack[previous_ply as usize])
+    }
+
+    // Gives a free move to the opponent, if our position is still too good with a reduced search
+    // then searching the actual moves would very likely fail high too
+    fn null_move_pruning(&mut self, beta: i16, depthleft: u8, ply: u8) -> Option<i16> {
+        let static_eval = evaluate(self.board, &mut self.pawn_table);
+        let previous_null_move = ply > 0 && self.null_move[ply as usize - 1];
+        if previous_null_move || self.null_move_verification || static_eval < beta || !self.board.has_non_pawn_material(self.board.to_move) {
+            return None;
+        }
+
+        // Deeper searches and larger eval margins are reduced more
+        let reduction = 3 + depthleft / 6 + ((static_eval as i32 - beta as i32) / 200).min(3) as u8;
+        let null_depth = depthleft.saturating_sub(reduction);
+
+        self.null_move[ply as usize] = true;
+        self.move_stack[ply as usize] = None;
+        let ext_move = self.board.make_null();
+        let score = -self.alphabeta(-beta, -beta+1, null_depth, ply+1);
+        self.board.unmake_null(ext_move);
+        self.null_move[ply as usize] = false;
+
+        if self.aborted || score < beta {
+            return None;
+        }
+
+        // Mates found after a null move are not proven
+        let score = if score >= MATE_BOUND {beta} else {score};
+
+        if depthleft < NULL_MOVE_VERIFICATION_DEPTH {
+            return Some(score);
+        }
+
+        // Zugzwang protection, the cutoff has to be confirmed without null moves
+        self.null_move_verification = true;
+        let verification = self.alphabeta(beta-1, beta, null_depth, ply);
+        self.null_move_verification = false;
+
+        (verification >= beta).then_some(score)
+    }
+
+    // Only searches captures and queen promotions to resolve the tactics left at the horizon
+    // When in check every evasion is searched and standing pat is not allowed
+    fn quiescence(&mut self, mut alpha: i16, beta: i16, ply: u8) -> i16 {
+        self.nodes += 1;
+        self.pv_length[ply as usize] = ply as usize;
+        self.seldepth = self.seldepth.max(ply);
+
+        if self.should_stop() {
+            return 0;
+        }
+
+        let stand_pat = evaluate(self.board, &mut self.pawn_table);
+        if ply as usize >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+
+        // Being mated is the worst outcome when no evasion is found
+        let in_check = self.board.in_check();
+        let mut max_score = -MATE_SCORE + ply as i16;
+
+        if !in_check {
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            max_score = stand_pat;
+        }
+
+        // The move picker generates the losing captures too but skips them once see_ge fails
+        let mut move_picker = MovePicker::new_quiescence(self.board);
+
+        while let Some(possible_move) = move_picker.next(self.board, &self.ordering) {
+            if !in_check {
+                // Delta pruning, even winning the captured piece for free is not enough
+                let captured_value = match possible_move.infos() {
+                    MoveInfo::EnPassantCapture => PAWN.value(),
+                    _ => self.board.squares[possible_move.to() as usize].map_or(0, |piece| piece.value()),
+                };
+                let promotion_value = match possible_move.infos() {
+                    MoveInfo::Promotion(piece) | MoveInfo::CapturePromotion(piece) => piece.value() - PAWN.value(),
+                    _ => 0,
+                };
+                if stand_pat.saturating_add((captured_value + promotion_value) as i16 + DELTA_MARGIN) <= alpha {
+                    continue;
+                }
+            }
+
+            self.move_stack[ply as usize] = Some(possible_move);
+            let ext_move = self.board.make(possible_move);
+            let score = -self.quiescence(-beta, -alpha, ply+1);
+            self.board.unmake(ext_move);
+
+            if self.aborted {
+                return 0;
+            }
+
+            if score > max_score {
+                max_score = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, possible_move);
+                }
+            }
+
+            if score >= beta {
+                return score;
+            }
+        }
+
+        max_score
+    }
 }
 
-impl PVNode {
-    fn new(pv_move: Move, evaluation: i16) -> Self {
-        Self { pv_move, evaluation }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syzygy::FIXTURES;
+
+    fn search(fen: &str, depth: u8) -> SearchResult {
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut tt = TranspositionTable::new(1);
+        Searcher::new(&mut board, &mut tt, Arc::new(AtomicBool::new(false))).search(depth)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_principal_variation() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let result = search(fen, 4);
+        assert_eq!(result.depth, 4);
+        assert_eq!(result.pv.len(), 4);
+        assert_eq!(result.best_move, Some(result.pv[0]));
+        assert_eq!(result.ponder_move, Some(result.pv[1]));
+
+        // The principal variation is a legal line
+        let mut board = Board::from_fen(fen).unwrap();
+        for m in result.pv {
+            assert!(board.legal_move_gen().contains(&m));
+            board.make(m);
+        }
+    }
+
+    #[test]
+    fn test_wins_material() {
+        // The queen is hanging
+        let result = search("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 3);
+        assert_eq!(result.best_move.map(Move::to_uci).as_deref(), Some("d2d5"));
+        assert!(result.score > 400);
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        // Back rank mate in one
+        let result = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3);
+        assert_eq!(result.best_move.map(Move::to_uci).as_deref(), Some("a1a8"));
+        assert_eq!(result.score, MATE_SCORE - 1);
+
+        // Mated and stalemated positions have no best move
+        let result = search("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", 3);
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, -MATE_SCORE);
+        let result = search("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, DRAW_SCORE);
+
+        // Black can only delay the mate
+        let result = search("k7/8/2K5/8/8/8/8/7R w - - 0 1", 4);
+        assert_eq!(result.score, MATE_SCORE - 3);
+    }
+
+    #[test]
+    fn test_aspiration_windows() {
+        // The mate is only found at depth 5, failing high out of the window around the depth 4 score
+        let fen = "k7/8/8/3K4/8/8/8/7R w - - 0 1";
+        let result = search(fen, 5);
+        assert_eq!(result.score, MATE_SCORE - 5);
+        assert_eq!(result.pv.len(), 5);
+
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut tt = TranspositionTable::new(1);
+        let mut searcher = Searcher::new(&mut board, &mut tt, Arc::new(AtomicBool::new(false)));
+        assert_eq!(searcher.alphabeta(-INFINITY, INFINITY, 5, 0), result.score);
+    }
+
+    #[test]
+    fn test_null_move_zugzwang() {
+        // Rf1 puts black in zugzwang, null moves would hide it from a deep enough search
+        let result = search("8/8/p1p5/1p5p/1P5p/8/PPP2K1p/4R1rk w - - 0 1", 12);
+        assert_eq!(result.best_move.map(Move::to_uci).as_deref(), Some("e1f1"));
+
+        // A tablebase loss is an ordinary beta, far below the static evaluation
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/QQQQK3 w - - 0 1").unwrap();
+        let mut tt = TranspositionTable::new(1);
+        let mut searcher = Searcher::new(&mut board, &mut tt, Arc::new(AtomicBool::new(false)));
+        let beta = -TB_WIN_SCORE + 2;
+        assert!(searcher.null_move_pruning(beta, 4, 1).is_some_and(|score| score >= beta));
+    }
+
+    fn search_with_tablebases(fen: &str, depth: u8) -> SearchResult {
+        let tablebases = Tablebases::new(FIXTURES);
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut tt = TranspositionTable::new(1);
+        Searcher::new(&mut board, &mut tt, Arc::new(AtomicBool::new(false))).with_tablebases(Some(&tablebases)).search(depth)
+    }
+
+    #[test]
+    fn test_tablebase_root() {
+        // Qd7 and Qd8 hang the queen, every other move wins
+        let tablebases = Tablebases::new(FIXTURES);
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        let mut tt = TranspositionTable::new(1);
+        let (result, root_moves) = {
+            let mut searcher = Searcher::new(&mut board, &mut tt, Arc::new(AtomicBool::new(false))).with_tablebases(Some(&tablebases));
+            (searcher.search(4), searcher.root_moves.take().unwrap())
+        };
+        assert!(root_moves.contains(&result.best_move.unwrap()));
+        assert!(root_moves.iter().all(|m| !["d1d7", "d1d8"].contains(&m.to_uci().as_str())));
+        for m in root_moves {
+            let ext_move = board.make(m);
+            assert_eq!(tablebases.probe_wdl(&mut board), Some(Wdl::Loss), "{}", m.to_uci());
+            board.unmake(ext_move);
+        }
+    }
+
+    #[test]
+    fn test_tablebase_probes() {
+        // Taking the knight leaves a drawn pawn ending, the knight would win the pawn otherwise
+        let result = search_with_tablebases("8/8/8/8/4k3/3n4/4P3/4K3 w - - 0 1", 4);
+        assert_eq!(result.best_move.map(Move::to_uci).as_deref(), Some("e2d3"));
+        assert_eq!(result.score, DRAW_SCORE);
+        assert!(result.tbhits > 0);
+
+        // Taking the knight wins
+        let result = search_with_tablebases("4k3/8/8/8/8/8/8/n2QK3 w - - 0 1", 3);
+        assert_eq!(result.best_move.map(Move::to_uci).as_deref(), Some("d1a1"));
+        assert_eq!(result.score, TB_WIN_SCORE - 1);
+
+        // In a PV node the probe bounds the score even when it doesn't cut
+        let tablebases = Tablebases::new(FIXTURES);
+        for (fen, score) in [("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1", TB_WIN_SCORE - 1), ("4k3/8/8/8/8/8/8/Q3K3 b - - 0 1", -TB_WIN_SCORE + 1)] {
+            let mut board = Board::from_fen(fen).unwrap();
+            let mut tt = TranspositionTable::new(1);
+            let mut searcher = Searcher::new(&mut board, &mut tt, Arc::new(AtomicBool::new(false))).with_tablebases(Some(&tablebases));
+            searcher.tb_probe_limit = tablebases.max_pieces() as u32;
+            assert_eq!(searcher.alphabeta(-INFINITY, INFINITY, 2, 1), score, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_quiescence() {
+        // Taking the defended pawn loses the queen, which a plain depth 1 search can't see
+        let result = search("4k3/8/2p5/3p4/8/8/3Q4/4K3 w - - 0 1", 1);
+        assert_ne!(result.best_move.map(Move::to_uci).as_deref(), Some("d2d5"));
+        assert!(result.score > 500);
+    }
+
+    #[test]
+    fn test_capture_gen() {
+        let board = Board::from_fen("4k3/1P6/8/3p4/4P3/8/8/4K2R w K - 0 1").unwrap();
+        let mut captures: Vec<String> = board.legal_capture_gen().into_iter().map(Move::to_uci).collect();
+        captures.sort();
+        assert_eq!(captures, ["b7b8q", "e4d5"]);
+
+        // In check all the evasions are generated
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1").unwrap();
+        assert_eq!(board.legal_capture_gen().len(), board.legal_move_gen().len());
+    }
+}
@@ -0,0 +1,902 @@
+// Writer of small tables in the Syzygy format, used to build the fixtures of the tests
+//
+// The positions of a table are found by decoding every index of its sections, without the encoding of the
+// reader: the pieces are split in groups of identical pieces, each group numbers its own placements and the
+// numbers are mixed in the encoding order written in the table. Each legal position must then be encoded
+// back to its index by the reader, which checks the two numberings against each other.
+//
+// Every position is solved by iterating over the moves until the outcomes are known, the moves changing
+// the material being probed in the tables written before. Distances to zeroing are counted in plies:
+// 1 for a zeroing win or a mate in one, -1 when mated, one more ply per non zeroing move. They are stored
+// minus one, and halved when the table has no even distance for the outcome.
+//
+// The values are then compressed like the real tables: the most frequent pairs of adjacent symbols are
+// replaced by a new symbol, and the symbols are written with a canonical Huffman code in fixed size blocks.
+// Once written the tables are read back and every position is probed against its solution.
+
+use std::{cmp::Reverse, collections::BinaryHeap, fs, path::Path, sync::LazyLock};
+
+use super::*;
+
+struct TableSpec {
+    name: &'static str,
+    // Encoding order of the first group and of the pawns of the second color, for each side to move
+    orders: [[u8; 2]; 2],
+    // How the DTZ values are mapped and the side to move they are stored for, only the tables
+    // of the fixtures have one
+    dtz: Option<(DtzMap, usize)>,
+}
+
+const FIRST_GROUP_FIRST: [[u8; 2]; 2] = [[0, 0xF], [0, 0xF]];
+
+// The tables reached by captures and promotions come first
+const TABLES: [TableSpec; 21] = [
+    TableSpec { name: "KBvK", orders: FIRST_GROUP_FIRST, dtz: Some((DtzMap::None, 0)) },
+    TableSpec { name: "KNvK", orders: FIRST_GROUP_FIRST, dtz: Some((DtzMap::None, 0)) },
+    TableSpec { name: "KQvK", orders: FIRST_GROUP_FIRST, dtz: Some((DtzMap::None, 0)) },
+    TableSpec { name: "KRvK", orders: FIRST_GROUP_FIRST, dtz: Some((DtzMap::Bytes, 1)) },
+    TableSpec { name: "KPvK", orders: FIRST_GROUP_FIRST, dtz: Some((DtzMap::Wide, 0)) },
+    // The kings are the first group, the knights are encoded first with white to move
+    TableSpec { name: "KNNvK", orders: [[1, 0xF], [0, 0xF]], dtz: Some((DtzMap::Bytes, 0)) },
+    // Reached by promotions from KPvKP
+    TableSpec { name: "KNvKN", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KBvKN", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KBvKB", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KRvKN", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KRvKB", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KRvKR", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KQvKN", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KQvKB", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KQvKR", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KQvKQ", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KNvKP", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KBvKP", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KRvKP", orders: FIRST_GROUP_FIRST, dtz: None },
+    TableSpec { name: "KQvKP", orders: FIRST_GROUP_FIRST, dtz: None },
+    // The black pawn is encoded first, then the white pawn leading the table
+    TableSpec { name: "KPvKP", orders: [[1, 0], [1, 0]], dtz: Some((DtzMap::None, 0)) },
+];
+
+const BLOCK_SIZE_LOG: u8 = 8;
+const SPAN_LOG: u8 = 10;
+// Codes always fit in the 33 bits at least left in the decoding buffer
+const MAX_CODE_LENGTH: usize = 32;
+// The number of values of a symbol minus one is stored on a byte
+const MAX_SYMBOL_VALUES: usize = 256;
+// Symbols are numbered on 12 bits, the last number marking the leaves
+const MAX_SYMBOLS: usize = 512;
+// Pairs seen less often are not worth a new symbol
+const MIN_PAIR_COUNT: u32 = 16;
+// Offsets in a block are stored on 16 bits in the sparse index
+const MAX_BLOCK_VALUES: usize = 1 << 15;
+
+// Outcomes of the indices that are not legal positions, and of the positions not solved yet
+const BROKEN: i8 = i8::MAX;
+const UNKNOWN: i8 = i8::MIN;
+// A move is either the id of the position it leads to in the table, with a flag when it zeroes
+// the fifty-move counter, or the outcome for the side playing it when it changes the material
+const OUTCOME_EDGE: u32 = 1 << 31;
+const ZEROING_EDGE: u32 = 1 << 30;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DtzMap {
+    None,
+    Bytes,
+    Wide,
+}
+
+// Writes the tables of the fixtures in the directory, the other ones only in a scratch directory,
+// and checks them
+pub fn write_tables(directory: &Path) {
+    let scratch = std::env::temp_dir().join("yace-syzygy");
+    let _ = fs::remove_dir_all(&scratch);
+    fs::create_dir_all(&scratch).expect("cannot create the scratch directory");
+    fs::create_dir_all(directory).expect("cannot create the table directory");
+
+    for spec in &TABLES {
+        let entry = TableEntry::new(spec.name, PathBuf::new(), None).unwrap();
+        let pieces = piece_codes(&entry);
+        let sections = sections(&entry, spec, &pieces);
+
+        let tablebases = Tablebases::new(scratch.to_str().unwrap());
+        let solution = solve(&entry, spec, &pieces, &sections, &tablebases);
+
+        let mut files = vec![(format!("{}.rtbw", spec.name), write_table(&entry, spec, &pieces, &sections, &solution, TableType::Wdl))];
+        if spec.dtz.is_some() {
+            files.push((format!("{}.rtbz", spec.name), write_table(&entry, spec, &pieces, &sections, &solution, TableType::Dtz)));
+        }
+        for (file_name, bytes) in files {
+            fs::write(scratch.join(&file_name), &bytes).expect("cannot write the table");
+            if spec.dtz.is_some() {
+                fs::write(directory.join(&file_name), &bytes).expect("cannot write the table");
+            }
+        }
+
+        let tablebases = Tablebases::new(scratch.to_str().unwrap());
+        for section in &sections {
+            for index in 0..section.layout.size {
+                let id = section.first + index as usize;
+                if solution.wdl[id] == BROKEN {
+                    continue;
+                }
+                let mut board = section_board(&entry, &pieces, section, index).unwrap();
+                assert_eq!(tablebases.probe_wdl(&mut board), Wdl::from_value(solution.wdl[id] as i32), "{}", board.to_fen());
+                if spec.dtz.is_some() {
+                    assert_eq!(tablebases.probe_dtz(&mut board), Some(solution.dtz[id] as i32), "{}", board.to_fen());
+                }
+            }
+        }
+    }
+}
+
+// Piece codes in the order of the table: the leading pawns, then the pawns of the other color, or without
+// pawns the kings when no piece is unique and the unique pieces otherwise, the identical pieces being together
+fn piece_codes(entry: &TableEntry) -> Vec<u8> {
+    let (white, black) = entry.key.split_once('v').unwrap();
+    let mut codes: Vec<u8> = white.chars().map(|piece| TB_PIECE_TYPES[piece_ordinal(piece).unwrap()])
+        .chain(black.chars().map(|piece| 8 | TB_PIECE_TYPES[piece_ordinal(piece).unwrap()]))
+        .collect();
+    let count = |code: u8, codes: &[u8]| codes.iter().filter(|&&other| other == code).count();
+    let lead_pawn = if entry.has_pawns && count(1, &codes) == entry.pawn_count[0] { 1 } else { 9 };
+    let keys: Vec<(usize, u8)> = codes.iter().map(|&code| {
+        let rank = if code == lead_pawn || (!entry.has_pawns && !entry.has_unique_pieces && code & 7 == 6) {
+            0
+        } else if code & 7 == 1 {
+            1
+        } else if !entry.has_pawns && count(code, &codes) == 1 {
+            2
+        } else {
+            3
+        };
+        (rank, code)
+    }).collect();
+    let mut order: Vec<usize> = (0..codes.len()).collect();
+    order.sort_by_key(|&i| keys[i]);
+    codes = order.into_iter().map(|i| keys[i].1).collect();
+    codes
+}
+
+// A section of a table, the positions of a side to move with the leading pawn on a file
+struct Section {
+    side: usize,
+    file: usize,
+    layout: Layout,
+    // Id of the index 0 among the positions of every section
+    first: usize,
+}
+
+fn sections(entry: &TableEntry, spec: &TableSpec, pieces: &[u8]) -> Vec<Section> {
+    let sides = if entry.symmetric { 1 } else { 2 };
+    let mut sections = Vec::new();
+    let mut first = 0;
+    for file in 0..if entry.has_pawns { 4 } else { 1 } {
+        for side in 0..sides {
+            let layout = Layout::new(entry, pieces, spec.orders[side], file);
+            let size = layout.size as usize;
+            sections.push(Section { side, file, layout, first });
+            first += size;
+        }
+    }
+    sections
+}
+
+// Groups of identical pieces of a section, in the order of the pieces of the table
+struct Layout {
+    groups: Vec<Group>,
+    both_pawns: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    size: u64,
+}
+
+struct Group {
+    len: usize,
+    // Number of placements of the group and multiplier of its placement in the index
+    count: u64,
+    factor: u64,
+}
+
+impl Layout {
+    fn new(entry: &TableEntry, pieces: &[u8], order: [u8; 2], file: usize) -> Self {
+        let both_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+        let run = |start: usize| pieces[start..].iter().take_while(|&&piece| piece == pieces[start]).count();
+        let first_len = if entry.has_pawns { run(0) } else if entry.has_unique_pieces { 3 } else { 2 };
+        let first_count = if entry.has_pawns {
+            (1..7).map(|rank| choose(pawn_priority(8 * rank + file), first_len - 1)).sum()
+        } else if entry.has_unique_pieces {
+            UNIQUE_PLACEMENTS
+        } else {
+            KING_PLACEMENTS.len() as u64
+        };
+
+        let mut groups = vec![Group { len: first_len, count: first_count, factor: 0 }];
+        let mut start = first_len;
+        while start < pieces.len() {
+            groups.push(Group { len: run(start), count: 0, factor: 0 });
+            start += run(start);
+        }
+        // The pawns of the second color are placed on the ranks 2 to 7 left by the leading pawns,
+        // the other pieces on the squares left by both
+        let mut free_squares = 64 - first_len;
+        if both_pawns {
+            groups[1].count = choose(48 - first_len, groups[1].len);
+            free_squares -= groups[1].len;
+        }
+        for group in groups.iter_mut().skip(1 + both_pawns as usize) {
+            group.count = choose(free_squares, group.len);
+            free_squares -= group.len;
+        }
+
+        // The first group is encoded at order[0], the pawns of the second color at order[1] and the other
+        // groups in between, the group encoded first varying the fastest
+        let mut others = 1 + both_pawns as usize..groups.len();
+        let mut factor = 1;
+        for k in 0..groups.len() as u8 {
+            let group = if k == order[0] {
+                0
+            } else if both_pawns && k == order[1] {
+                1
+            } else {
+                others.next().expect("invalid encoding order")
+            };
+            groups[group].factor = factor;
+            factor *= groups[group].count;
+        }
+
+        Layout {
+            groups,
+            both_pawns,
+            has_pawns: entry.has_pawns,
+            has_unique_pieces: entry.has_unique_pieces,
+            size: factor,
+        }
+    }
+
+    // Squares of the pieces at index, in the order of the pieces of the table
+    fn decode(&self, file: usize, index: u64) -> Vec<usize> {
+        let mut squares = Vec::new();
+        for (i, group) in self.groups.iter().enumerate() {
+            let number = index / group.factor % group.count;
+            if i == 0 {
+                if self.has_pawns {
+                    squares.extend(lead_pawns(group.len, file, number));
+                } else if self.has_unique_pieces {
+                    squares.extend(unique_pieces(number));
+                } else {
+                    let (first, second) = KING_PLACEMENTS[number as usize];
+                    squares.extend([first, second]);
+                }
+                continue;
+            }
+
+            // Numbered among the squares left free by the previous groups
+            let pawns = self.both_pawns && i == 1;
+            let mut taken = squares.clone();
+            taken.sort_unstable();
+            for free_square in combination(number, group.len) {
+                let mut sq = free_square + if pawns { 8 } else { 0 };
+                for &taken in &taken {
+                    if taken <= sq {
+                        sq += 1;
+                    }
+                }
+                squares.push(sq);
+            }
+        }
+        squares
+    }
+}
+
+fn choose(n: usize, k: usize) -> u64 {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |result, i| result * (n - i) as u64 / (i + 1) as u64)
+}
+
+// Ascending numbers c0 < c1 < ... numbered by the sum of choose(ci, i + 1)
+fn combination(mut number: u64, len: usize) -> Vec<usize> {
+    let mut numbers = vec![0; len];
+    for i in (0..len).rev() {
+        let mut c = i;
+        while choose(c + 1, i + 1) <= number {
+            c += 1;
+        }
+        numbers[i] = c;
+        number -= choose(c, i + 1);
+    }
+    numbers
+}
+
+fn file_of(sq: usize) -> usize {
+    sq % 8
+}
+
+fn rank_of(sq: usize) -> usize {
+    sq / 8
+}
+
+// 47 for a2, 46 for h2, 45 for a3 up to 36 for h7, then from 35 on the b and g files towards the center:
+// the leading pawn is the one with the highest priority, the others having a lower one
+fn pawn_priority(sq: usize) -> usize {
+    let file = file_of(sq);
+    47 - 12 * file.min(7 - file) - 2 * (rank_of(sq) - 1) - (file > 3) as usize
+}
+
+// Leading pawns numbered by the square of the leading one from rank 2 to 7 on the file, then by
+// the combination of the priorities of the others
+fn lead_pawns(len: usize, file: usize, mut number: u64) -> Vec<usize> {
+    for rank in 1..7 {
+        let lead = 8 * rank + file;
+        let placements = choose(pawn_priority(lead), len - 1);
+        if number >= placements {
+            number -= placements;
+            continue;
+        }
+        let mut squares = vec![lead];
+        for priority in combination(number, len - 1) {
+            squares.push((8..56).find(|&sq| pawn_priority(sq) == priority).unwrap());
+        }
+        return squares;
+    }
+    unreachable!("leading pawns out of the section");
+}
+
+// Squares of the a1-d1-d4 triangle below the diagonal, then on it
+const TRIANGLE: [usize; 10] = [1, 2, 3, 10, 11, 19, 0, 9, 18, 27];
+
+fn below_diagonal(sq: usize) -> bool {
+    rank_of(sq) < file_of(sq)
+}
+
+fn on_diagonal(sq: usize) -> bool {
+    rank_of(sq) == file_of(sq)
+}
+
+// The first king is in the triangle and the second one below the diagonal when the first is on it,
+// the placements with both kings on the diagonal being the last ones
+static KING_PLACEMENTS: LazyLock<Vec<(usize, usize)>> = LazyLock::new(|| {
+    let mut placements = Vec::new();
+    let mut both_on_diagonal = Vec::new();
+    for first in TRIANGLE {
+        for second in 0..64 {
+            let distance = file_of(first).abs_diff(file_of(second)).max(rank_of(first).abs_diff(rank_of(second)));
+            if distance <= 1 || (on_diagonal(first) && !on_diagonal(second) && !below_diagonal(second)) {
+                continue;
+            }
+            if on_diagonal(first) && on_diagonal(second) {
+                both_on_diagonal.push((first, second));
+            } else {
+                placements.push((first, second));
+            }
+        }
+    }
+    placements.extend(both_on_diagonal);
+    assert_eq!(placements.len(), 462);
+    placements
+});
+
+const UNIQUE_PLACEMENTS: u64 = 6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + 4 * 7 * 6;
+
+// Number of a square among the other ones, the taken squares being skipped
+fn skip_taken(mut number: usize, taken: &[usize]) -> usize {
+    let mut taken = taken.to_vec();
+    taken.sort_unstable();
+    for taken in taken {
+        if taken <= number {
+            number += 1;
+        }
+    }
+    number
+}
+
+// Three unique pieces: the first in the triangle below the diagonal and the others anywhere, or the first
+// on the diagonal and the second below it, or both on the diagonal and the third below it, or all three
+// on the diagonal, the squares on the diagonal being numbered by their rank
+fn unique_pieces(number: u64) -> [usize; 3] {
+    let below: Vec<usize> = (0..64).filter(|&sq| below_diagonal(sq)).collect();
+    let diagonal = |rank: usize| 9 * rank;
+    let mut number = number as usize;
+
+    if number < 6 * 63 * 62 {
+        let first = TRIANGLE[number / (63 * 62)];
+        let second = skip_taken(number / 62 % 63, &[first]);
+        return [first, second, skip_taken(number % 62, &[first, second])];
+    }
+    number -= 6 * 63 * 62;
+    if number < 4 * 28 * 62 {
+        let first = diagonal(number / (28 * 62));
+        let second = below[number / 62 % 28];
+        return [first, second, skip_taken(number % 62, &[first, second])];
+    }
+    number -= 4 * 28 * 62;
+    if number < 4 * 7 * 28 {
+        let first_rank = number / (7 * 28);
+        let second_rank = skip_taken(number / 28 % 7, &[first_rank]);
+        return [diagonal(first_rank), diagonal(second_rank), below[number % 28]];
+    }
+    number -= 4 * 7 * 28;
+    let first_rank = number / (7 * 6);
+    let second_rank = skip_taken(number / 6 % 7, &[first_rank]);
+    let third_rank = skip_taken(number % 6, &[first_rank, second_rank]);
+    [diagonal(first_rank), diagonal(second_rank), diagonal(third_rank)]
+}
+
+// None if the index is not a legal position
+fn section_board(entry: &TableEntry, pieces: &[u8], section: &Section, index: u64) -> Option<Board> {
+    let squares = section.layout.decode(section.file, index);
+    let mut board_squares = [None; 64];
+    for (&piece, &sq) in pieces.iter().zip(&squares) {
+        board_squares[sq] = Some(piece);
+    }
+
+    let mut fen = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match board_squares[8 * rank + file] {
+                Some(code) => {
+                    if empty > 0 {
+                        fen.push(char::from_digit(empty, 10).unwrap());
+                        empty = 0;
+                    }
+                    let piece = b" PNBRQK"[(code & 7) as usize] as char;
+                    fen.push(if code & 8 != 0 { piece.to_ascii_lowercase() } else { piece });
+                },
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push(char::from_digit(empty, 10).unwrap());
+        }
+        if rank > 0 {
+            fen.push('/');
+        }
+    }
+    // Symmetric tables only store white to move
+    let to_move = if section.side == 0 || entry.symmetric { " w" } else { " b" };
+    fen.push_str(to_move);
+    fen.push_str(" - - 0 1");
+    Board::from_fen(&fen).ok()
+}
+
+// Outcome of every index of the sections, and distance to zeroing for the tables with a DTZ table
+struct Solution {
+    wdl: Vec<i8>,
+    dtz: Vec<i16>,
+}
+
+fn solve(entry: &TableEntry, spec: &TableSpec, pieces: &[u8], sections: &[Section], tablebases: &Tablebases) -> Solution {
+    // Sections of the reader, to find the index of the position reached by a move
+    let mut reader = TableData { bytes: Vec::new(), items: Default::default(), map: 0 };
+    for section in sections {
+        let data = &mut reader.items[section.side][section.file];
+        data.pieces[..pieces.len()].copy_from_slice(pieces);
+        data.set_groups(entry, spec.orders[section.side], section.file);
+    }
+    let id = |(side, file, index): (usize, usize, u64)| {
+        let section = sections.iter().find(|section| section.side == side && section.file == file).unwrap();
+        section.first + index as usize
+    };
+
+    let size = sections.iter().map(|section| section.layout.size as usize).sum();
+    let mut wdl = vec![BROKEN; size];
+    let mut offsets = vec![0u32; size + 1];
+    let mut edges = Vec::new();
+    for section in sections {
+        for index in 0..section.layout.size {
+            let node = section.first + index as usize;
+            if let Some(mut board) = section_board(entry, pieces, section, index) {
+                let slot = encode(entry, &reader, TableType::Wdl, &board, &entry.key);
+                assert_eq!(slot, Some((section.side, section.file, index)), "{} is not encoded at its index", board.to_fen());
+
+                let moves = board.legal_move_gen();
+                wdl[node] = if !moves.is_empty() { UNKNOWN } else if board.in_check() { Wdl::Loss as i8 } else { Wdl::Draw as i8 };
+                for m in moves {
+                    let zeroing = is_zeroing(&board, m);
+                    let material_change = is_capture(m) || matches!(m.infos(), MoveInfo::Promotion(_) | MoveInfo::CapturePromotion(_));
+                    let ext_move = board.make(m);
+                    edges.push(if material_change {
+                        let outcome = -tablebases.probe_wdl(&mut board).unwrap_or_else(|| panic!("no table for {}", board.to_fen()));
+                        OUTCOME_EDGE | (outcome as i32 + 2) as u32
+                    } else {
+                        let child = id(encode(entry, &reader, TableType::Wdl, &board, &entry.key).unwrap());
+                        child as u32 | if zeroing { ZEROING_EDGE } else { 0 }
+                    });
+                    board.unmake(ext_move);
+                }
+            }
+            offsets[node + 1] = edges.len() as u32;
+        }
+    }
+    assert!(edges.iter().all(|&edge| edge & OUTCOME_EDGE != 0 || wdl[(edge & !ZEROING_EDGE) as usize] != BROKEN), "a move leads to an illegal index");
+
+    solve_wdl(&offsets, &edges, &mut wdl);
+    let dtz = if spec.dtz.is_some() { solve_dtz(&offsets, &edges, &wdl) } else { Vec::new() };
+    Solution { wdl, dtz }
+}
+
+// Outcome of a move for the side playing it, None while the position it leads to is not solved
+fn edge_outcome(edge: u32, wdl: &[i8]) -> Option<i8> {
+    if edge & OUTCOME_EDGE != 0 {
+        return Some((edge & 7) as i8 - 2);
+    }
+    match wdl[(edge & !ZEROING_EDGE) as usize] {
+        UNKNOWN => None,
+        child => Some(-child),
+    }
+}
+
+// A position is won when a move leads to a lost one, and lost when every move leads to a won one
+// The positions left once nothing changes anymore are draws, the tables being too small for the
+// fifty-move rule to matter
+fn solve_wdl(offsets: &[u32], edges: &[u32], wdl: &mut [i8]) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in 0..wdl.len() {
+            if wdl[node] != UNKNOWN {
+                continue;
+            }
+            let moves = &edges[offsets[node] as usize..offsets[node + 1] as usize];
+            let outcomes = moves.iter().map(|&edge| edge_outcome(edge, wdl));
+            if outcomes.clone().any(|outcome| outcome == Some(Wdl::Win as i8)) {
+                wdl[node] = Wdl::Win as i8;
+                changed = true;
+            } else if outcomes.into_iter().all(|outcome| outcome == Some(Wdl::Loss as i8)) {
+                wdl[node] = Wdl::Loss as i8;
+                changed = true;
+            }
+        }
+    }
+
+    for outcome in wdl.iter_mut().filter(|outcome| **outcome == UNKNOWN) {
+        *outcome = Wdl::Draw as i8;
+    }
+}
+
+// The distances are found in increasing order, a won position being at one ply more than the closest
+// lost position it can reach and a lost position at one ply more than the farthest won position
+fn solve_dtz(offsets: &[u32], edges: &[u32], wdl: &[i8]) -> Vec<i16> {
+    const UNSOLVED: i16 = i16::MIN;
+    let mut dtz: Vec<i16> = wdl.iter().map(|&outcome| if outcome == Wdl::Win as i8 || outcome == Wdl::Loss as i8 { UNSOLVED } else { 0 }).collect();
+    let mut unsolved: Vec<usize> = (0..dtz.len()).filter(|&node| dtz[node] == UNSOLVED).collect();
+    let mut distance = 1;
+    while !unsolved.is_empty() {
+        assert!(distance <= 100, "the fifty-move rule is not handled");
+        let ply = |edge: u32| {
+            let child = (edge & !ZEROING_EDGE) as usize;
+            if edge & (OUTCOME_EDGE | ZEROING_EDGE) != 0 || offsets[child] == offsets[child + 1] {
+                Some(1)
+            } else {
+                (dtz[child] != UNSOLVED).then(|| dtz[child].abs() + 1)
+            }
+        };
+        let found: Vec<usize> = unsolved.iter().copied().filter(|&node| {
+            let moves = &edges[offsets[node] as usize..offsets[node + 1] as usize];
+            if wdl[node] == Wdl::Win as i8 {
+                moves.iter().any(|&edge| edge_outcome(edge, wdl) == Some(Wdl::Win as i8) && ply(edge) == Some(distance))
+            } else {
+                moves.iter().try_fold(1, |farthest, &edge| ply(edge).map(|ply| farthest.max(ply))) == Some(distance)
+            }
+        }).collect();
+        for &node in &found {
+            dtz[node] = if wdl[node] == Wdl::Win as i8 { distance } else { -distance };
+        }
+        unsolved.retain(|&node| dtz[node] == UNSOLVED);
+        distance += 1;
+    }
+    dtz
+}
+
+fn write_table(entry: &TableEntry, spec: &TableSpec, pieces: &[u8], sections: &[Section], solution: &Solution, table_type: TableType) -> Vec<u8> {
+    let both_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+    let (dtz_map, dtz_side) = spec.dtz.unwrap_or((DtzMap::None, 0));
+    let mut bytes = if table_type == TableType::Wdl { WDL_MAGIC } else { DTZ_MAGIC }.to_vec();
+    bytes.push(if entry.symmetric { 0 } else { SPLIT_FLAG } | if entry.has_pawns { HAS_PAWNS_FLAG } else { 0 });
+
+    // The DTZ table only has the sections of its side to move, numbered like the WDL ones
+    let orders = if table_type == TableType::Wdl { spec.orders } else { [spec.orders[dtz_side]; 2] };
+    for _ in 0..if entry.has_pawns { 4 } else { 1 } {
+        bytes.push(orders[0][0] | orders[1][0] << 4);
+        if both_pawns {
+            bytes.push(orders[0][1] | orders[1][1] << 4);
+        }
+        bytes.extend(pieces.iter().map(|&piece| piece | piece << 4));
+    }
+    bytes.resize(bytes.len() + (bytes.len() & 1), 0);
+
+    let mut compressed = Vec::new();
+    let mut maps = Vec::new();
+    for section in sections {
+        let range = section.first..section.first + section.layout.size as usize;
+        let mut values = vec![None; range.len()];
+        let mut flags = 0;
+        match table_type {
+            TableType::Wdl => {
+                for (value, &wdl) in values.iter_mut().zip(&solution.wdl[range]) {
+                    if wdl != BROKEN {
+                        *value = Some((wdl as i32 + 2) as u16);
+                    }
+                }
+            },
+            TableType::Dtz => {
+                if section.side != dtz_side {
+                    continue;
+                }
+                flags |= dtz_side as u8 * STM_FLAG;
+                let stored: Vec<(usize, i8, i32)> = range.clone()
+                    .filter(|&id| solution.wdl[id] == Wdl::Win as i8 || solution.wdl[id] == Wdl::Loss as i8)
+                    .map(|id| (id - section.first, solution.wdl[id], solution.dtz[id].abs() as i32))
+                    .collect();
+
+                // Distances are stored in moves when they are all odd
+                let mut wdl_maps = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+                for (wdl, map_index, plies_flag) in [(Wdl::Win, 0, WIN_PLIES_FLAG), (Wdl::Loss, 1, LOSS_PLIES_FLAG)] {
+                    let distances = stored.iter().filter(|&&(_, outcome, _)| outcome == wdl as i8).map(|&(_, _, distance)| distance);
+                    let in_plies = distances.clone().any(|distance| distance % 2 == 0);
+                    if in_plies {
+                        flags |= plies_flag;
+                    }
+                    let mut raw: Vec<u16> = distances.map(|distance| (if in_plies { distance - 1 } else { (distance - 1) / 2 }) as u16).collect();
+                    raw.sort_unstable();
+                    raw.dedup();
+                    wdl_maps[map_index] = raw;
+                }
+
+                for (index, outcome, distance) in stored {
+                    let (map_index, plies_flag) = if outcome == Wdl::Win as i8 { (0, WIN_PLIES_FLAG) } else { (1, LOSS_PLIES_FLAG) };
+                    let raw = (if flags & plies_flag != 0 { distance - 1 } else { (distance - 1) / 2 }) as u16;
+                    values[index] = Some(if dtz_map == DtzMap::None { raw } else { wdl_maps[map_index].binary_search(&raw).unwrap() as u16 });
+                }
+                if dtz_map != DtzMap::None {
+                    flags |= MAPPED_FLAG | if dtz_map == DtzMap::Wide { WIDE_FLAG } else { 0 };
+                    maps.push(wdl_maps);
+                }
+            },
+        }
+
+        // Positions never probed take the value before them, to compress better
+        let first = values.iter().flatten().next().copied().unwrap_or(0);
+        let values: Vec<u16> = values.iter().scan(first, |previous, value| {
+            *previous = value.unwrap_or(*previous);
+            Some(*previous)
+        }).collect();
+        compressed.push(compress(&values, flags));
+    }
+
+    for section in &compressed {
+        bytes.extend(&section.sizes);
+    }
+    if table_type == TableType::Dtz {
+        for wdl_maps in maps {
+            if dtz_map == DtzMap::Wide {
+                bytes.resize(bytes.len() + (bytes.len() & 1), 0);
+            }
+            for map in wdl_maps {
+                if dtz_map == DtzMap::Wide {
+                    bytes.extend((map.len() as u16).to_le_bytes());
+                    bytes.extend(map.iter().flat_map(|value| value.to_le_bytes()));
+                } else {
+                    bytes.push(map.len() as u8);
+                    bytes.extend(map.iter().map(|&value| u8::try_from(value).expect("byte map value")));
+                }
+            }
+        }
+        bytes.resize(bytes.len() + (bytes.len() & 1), 0);
+    }
+    for section in &compressed {
+        bytes.extend(&section.sparse_index);
+    }
+    for section in &compressed {
+        bytes.extend(&section.block_lengths);
+    }
+    for section in &compressed {
+        bytes.resize((bytes.len() + 0x3F) & !0x3F, 0);
+        bytes.extend(&section.blocks);
+    }
+    bytes
+}
+
+// A compressed section of a table, the compression parameters and the data being stored apart
+struct Compressed {
+    sizes: Vec<u8>,
+    sparse_index: Vec<u8>,
+    block_lengths: Vec<u8>,
+    blocks: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+enum Symbol {
+    Leaf(u16),
+    Pair(usize, usize),
+}
+
+fn compress(values: &[u16], flags: u8) -> Compressed {
+    if values.iter().all(|&value| value == values[0]) {
+        return Compressed {
+            sizes: vec![flags | SINGLE_VALUE_FLAG, u8::try_from(values[0]).expect("single value")],
+            sparse_index: Vec::new(),
+            block_lengths: Vec::new(),
+            blocks: Vec::new(),
+        };
+    }
+
+    let mut leaves: Vec<u16> = values.to_vec();
+    leaves.sort_unstable();
+    leaves.dedup();
+    let mut symbols: Vec<Symbol> = leaves.iter().map(|&value| Symbol::Leaf(value)).collect();
+    let mut symbol_values = vec![1; symbols.len()];
+    let mut stream: Vec<usize> = values.iter().map(|value| leaves.binary_search(value).unwrap()).collect();
+
+    // Recursive pairing, the most frequent pair gets a symbol until no pair is frequent enough
+    let mut counts = vec![0u32; MAX_SYMBOLS * MAX_SYMBOLS];
+    while symbols.len() < MAX_SYMBOLS {
+        counts.fill(0);
+        for pair in stream.windows(2) {
+            if symbol_values[pair[0]] + symbol_values[pair[1]] <= MAX_SYMBOL_VALUES {
+                counts[pair[0] * MAX_SYMBOLS + pair[1]] += 1;
+            }
+        }
+        // The first of the most frequent pairs
+        let (pair, &count) = counts.iter().enumerate().rev().max_by_key(|&(_, count)| count).unwrap();
+        if count < MIN_PAIR_COUNT {
+            break;
+        }
+        let pair = (pair / MAX_SYMBOLS, pair % MAX_SYMBOLS);
+
+        let symbol = symbols.len();
+        symbols.push(Symbol::Pair(pair.0, pair.1));
+        symbol_values.push(symbol_values[pair.0] + symbol_values[pair.1]);
+        let mut paired = Vec::with_capacity(stream.len());
+        let mut i = 0;
+        while i < stream.len() {
+            if i + 1 < stream.len() && (stream[i], stream[i+1]) == pair {
+                paired.push(symbol);
+                i += 2;
+            } else {
+                paired.push(stream[i]);
+                i += 1;
+            }
+        }
+        stream = paired;
+    }
+
+    // Canonical code, the symbols are numbered from the longest codes and the longest codes are the
+    // numerically lowest
+    let mut frequencies = vec![0; symbols.len()];
+    for &symbol in &stream {
+        frequencies[symbol] += 1;
+    }
+    let lengths = code_lengths(&frequencies);
+    let (min_length, max_length) = (*lengths.iter().min().unwrap(), *lengths.iter().max().unwrap());
+    let mut order: Vec<usize> = (0..symbols.len()).collect();
+    order.sort_by_key(|&symbol| Reverse(lengths[symbol]));
+    let mut numbers = vec![0; symbols.len()];
+    for (number, &symbol) in order.iter().enumerate() {
+        numbers[symbol] = number;
+    }
+
+    let mut counts = vec![0; max_length + 1];
+    for &length in &lengths {
+        counts[length] += 1;
+    }
+    let mut lowest = vec![0; max_length + 1];
+    let mut base = vec![0u64; max_length + 1];
+    for length in (min_length..max_length).rev() {
+        lowest[length] = lowest[length + 1] + counts[length + 1];
+        assert_eq!((base[length + 1] + counts[length + 1] as u64) % 2, 0, "incomplete code");
+        base[length] = (base[length + 1] + counts[length + 1] as u64) / 2;
+    }
+    let code = |symbol: usize| base[lengths[symbol]] + (numbers[symbol] - lowest[lengths[symbol]]) as u64;
+
+    let mut sizes = vec![flags, BLOCK_SIZE_LOG, SPAN_LOG, 0];
+    let block_size = 1 << BLOCK_SIZE_LOG;
+    let mut blocks = Vec::new();
+    let mut block_values = Vec::new();
+    let mut writer = BitWriter::default();
+    let mut values_in_block = 0;
+    for &symbol in &stream {
+        let length = lengths[symbol];
+        if writer.bits + length > 8 * block_size || values_in_block + symbol_values[symbol] > MAX_BLOCK_VALUES {
+            blocks.extend(writer.finish(block_size));
+            block_values.push(values_in_block);
+            values_in_block = 0;
+        }
+        writer.push(code(symbol), length);
+        values_in_block += symbol_values[symbol];
+    }
+    blocks.extend(writer.finish(block_size));
+    block_values.push(values_in_block);
+
+    sizes.extend((block_values.len() as u32).to_le_bytes());
+    sizes.extend([max_length as u8, min_length as u8]);
+    sizes.extend(lowest[min_length..].iter().flat_map(|&lowest| (lowest as u16).to_le_bytes()));
+    sizes.extend((symbols.len() as u16).to_le_bytes());
+    for &symbol in &order {
+        let (left, right) = match symbols[symbol] {
+            Symbol::Leaf(value) => (value as usize, 0xFFF),
+            Symbol::Pair(left, right) => (numbers[left], numbers[right]),
+        };
+        sizes.extend([left as u8, (left >> 8) as u8 | (right << 4) as u8, (right >> 4) as u8]);
+    }
+    sizes.resize(sizes.len() + (symbols.len() & 1), 0);
+
+    // The sparse index locates the middle of each span, past the end in the last block for the last one
+    let block_starts: Vec<usize> = block_values.iter().scan(0, |start, &count| {
+        let block_start = *start;
+        *start += count;
+        Some(block_start)
+    }).collect();
+    let span = 1 << SPAN_LOG;
+    let mut sparse_index = Vec::new();
+    for k in 0..values.len().div_ceil(span) {
+        let index = k * span + span / 2;
+        let block = block_starts.partition_point(|&start| start <= index) - 1;
+        sparse_index.extend((block as u32).to_le_bytes());
+        sparse_index.extend(u16::try_from(index - block_starts[block]).expect("sparse index offset").to_le_bytes());
+    }
+    let block_lengths = block_values.iter().flat_map(|&count| (count as u16 - 1).to_le_bytes()).collect();
+
+    Compressed { sizes, sparse_index, block_lengths, blocks }
+}
+
+// Huffman code lengths, the frequencies being flattened until the longest code is short enough
+fn code_lengths(frequencies: &[usize]) -> Vec<usize> {
+    let mut weights = frequencies.to_vec();
+    loop {
+        let mut heap: BinaryHeap<Reverse<(usize, usize)>> = weights.iter().enumerate().map(|(node, &weight)| Reverse((weight, node))).collect();
+        let mut parents = vec![0; weights.len()];
+        while heap.len() > 1 {
+            let Reverse((first_weight, first)) = heap.pop().unwrap();
+            let Reverse((second_weight, second)) = heap.pop().unwrap();
+            let node = parents.len();
+            parents.push(node);
+            parents[first] = node;
+            parents[second] = node;
+            heap.push(Reverse((first_weight + second_weight, node)));
+        }
+
+        let root = parents.len() - 1;
+        let lengths: Vec<usize> = (0..weights.len()).map(|mut node| {
+            let mut length = 0;
+            while node != root {
+                node = parents[node];
+                length += 1;
+            }
+            length
+        }).collect();
+        if lengths.iter().all(|&length| length <= MAX_CODE_LENGTH) {
+            return lengths;
+        }
+        weights.iter_mut().for_each(|weight| *weight = *weight / 2 + 1);
+    }
+}
+
+// Codes packed from the most significant bit, each block being padded with zeros
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn push(&mut self, code: u64, length: usize) {
+        for bit in (0..length).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if code >> bit & 1 != 0 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+
+    fn finish(&mut self, block_size: usize) -> Vec<u8> {
+        let mut block = std::mem::take(&mut self.bytes);
+        block.resize(block_size, 0);
+        self.bits = 0;
+        block
+    }
+}
@@ -0,0 +1,1222 @@
+// Syzygy endgame tablebases
+//
+// Each material configuration has two files named after it, e.g. KRPvKR.rtbw and KRPvKR.rtbz:
+// - rtbw: win/draw/loss of every position, for both sides to move
+// - rtbz: distance to zeroing (the next capture or pawn move) of the winning and losing positions,
+//   for one side to move only
+//
+// Positions are encoded as an index computed from the piece squares, after mirroring them to a
+// canonical orientation, and the values are stored compressed with a Huffman code over symbols that
+// expand to pairs of symbols, in blocks of known length. Captures (and pawn moves for DTZ) are
+// not always stored correctly in the tables, they are resolved with a small search before probing.
+// Positions with castling rights are never found in the tables.
+//
+// Tables are read in memory on their first probe and kept until the tablebases are dropped.
+
+use std::{cmp::Ordering, collections::HashMap, fs, ops::Neg, path::{Path, PathBuf}, sync::{LazyLock, OnceLock}};
+
+use bit_iter::BitIter;
+
+use crate::board::*;
+
+// Writer of small tables, only needed to regenerate the fixtures of the tests
+#[cfg(test)]
+mod generate;
+
+// Tables up to 3 pieces, KNNvK and KPvKP written by the generate module
+#[cfg(test)]
+pub const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/syzygy");
+
+pub const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Flags of the header of a table
+const SPLIT_FLAG: u8 = 1;
+const HAS_PAWNS_FLAG: u8 = 2;
+
+// Flags of each compressed data section
+const STM_FLAG: u8 = 1;
+const MAPPED_FLAG: u8 = 2;
+const WIN_PLIES_FLAG: u8 = 4;
+const LOSS_PLIES_FLAG: u8 = 8;
+const WIDE_FLAG: u8 = 16;
+const SINGLE_VALUE_FLAG: u8 = 128;
+
+// Number of encodings of three unique pieces and of the two kings, with the symmetries removed
+const UNIQUE_PIECES_SIZE: u64 = 31332;
+const KINGS_SIZE: u64 = 462;
+
+// Type of each piece in the table files, indexed by the piece ordinal, from pawn 1 to king 6
+const TB_PIECE_TYPES: [u8; 6] = [5, 4, 3, 2, 1, 6];
+
+// Rank of the root moves converting a win before the fifty-move rule
+const MAX_DTZ: i32 = 1 << 18;
+
+// Outcome for the side to move, cursed wins and blessed losses are draws under the fifty-move rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Option<Wdl> {
+        match value {
+            -2 => Some(Wdl::Loss),
+            -1 => Some(Wdl::BlessedLoss),
+            0 => Some(Wdl::Draw),
+            1 => Some(Wdl::CursedWin),
+            2 => Some(Wdl::Win),
+            _ => None,
+        }
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32)).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TableType {
+    Wdl,
+    Dtz,
+}
+
+enum TableProbe {
+    Value(i32),
+    // DTZ tables only store one side to move
+    WrongSideToMove,
+}
+
+// Index tables shared by every table
+struct Encoding {
+    // Pawn squares ordered from the a and h files towards the center, and from rank 2 to 7
+    map_pawns: [usize; 64],
+    // Squares below the a1-h8 diagonal
+    map_b1h1h7: [usize; 64],
+    // Squares of the a1-d1-d4 triangle, the diagonal last
+    map_a1d1d4: [usize; 64],
+    // Legal placements of the two kings, indexed by the first king in the triangle
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 7],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+static ENCODING: LazyLock<Encoding> = LazyLock::new(Encoding::new);
+
+impl Encoding {
+    fn new() -> Self {
+        let mut encoding = Encoding {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 7],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_diagonal(sq) < 0 {
+                encoding.map_b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        let mut code = 0;
+        for sq in 0..=27 {
+            if off_diagonal(sq) < 0 && sq % 8 <= 3 {
+                encoding.map_a1d1d4[sq] = code;
+                code += 1;
+            }
+        }
+        for sq in (0..=27).step_by(9) {
+            encoding.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        // Both kings on the diagonal are encoded last
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for index in 0..10 {
+            for first in 0..=27 {
+                // b1 is the only square mapped to 0
+                if encoding.map_a1d1d4[first] != index || (index == 0 && first != 1) {
+                    continue;
+                }
+                for second in 0..64 {
+                    if (KING_ATTACK[first] | (first as Square).as_bitboard()).has(second as Square) {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) > 0 {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        diagonal.push((first, second));
+                    } else {
+                        encoding.map_kk[index][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (first, second) in diagonal {
+            encoding.map_kk[encoding.map_a1d1d4[first]][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..encoding.binomial.len().min(n + 1) {
+                encoding.binomial[k][n] = if k > 0 { encoding.binomial[k-1][n-1] } else { 0 }
+                    + if k < n { encoding.binomial[k][n-1] } else { 0 };
+            }
+        }
+
+        // Squares of the ranks 2 to 7 left to number
+        let mut available_squares = 48;
+        for lead_pawns_count in 1..=5 {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let sq = 8 * rank + file;
+                    if lead_pawns_count == 1 {
+                        encoding.map_pawns[sq] = available_squares - 1;
+                        encoding.map_pawns[sq ^ 7] = available_squares - 2;
+                        available_squares -= 2;
+                    }
+                    encoding.lead_pawn_idx[lead_pawns_count][sq] = index;
+                    index += encoding.binomial[lead_pawns_count - 1][encoding.map_pawns[sq]];
+                }
+                encoding.lead_pawns_size[lead_pawns_count][file] = index;
+            }
+        }
+
+        encoding
+    }
+}
+
+// Positive above the a1-h8 diagonal
+fn off_diagonal(sq: usize) -> i32 {
+    (sq / 8) as i32 - (sq % 8) as i32
+}
+
+// Decoding state of one compressed section of a table
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    max_sym_len: usize,
+    min_sym_len: usize,
+    num_blocks: usize,
+    block_size: usize,
+    // Number of values between two entries of the sparse index
+    span: usize,
+    // Offsets in the table file
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    // First code of each length, left aligned
+    base64: Vec<u64>,
+    // Number of values a symbol expands to, minus one
+    symlen: Vec<u8>,
+    // Piece codes in encoding order, color * 8 + type with type 1 to 6 from pawn to king
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    // Start of the DTZ value map of each WDL outcome
+    map_idx: [usize; 4],
+}
+
+// A table file read in memory
+struct TableData {
+    bytes: Vec<u8>,
+    // Indexed by the side to move (WDL only) and the file of the leading pawn (pawn tables only)
+    items: [[PairsData; 4]; 2],
+    // Offset of the DTZ value maps
+    map: usize,
+}
+
+struct TableEntry {
+    // Material of the stronger side first, e.g. KRPvKR
+    key: String,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    // Pawns of the leading color, the one with the fewest pawns, then of the other color
+    pawn_count: [usize; 2],
+    symmetric: bool,
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<TableData>>,
+    dtz: OnceLock<Option<TableData>>,
+}
+
+#[derive(Default)]
+pub struct Tablebases {
+    entries: Vec<TableEntry>,
+    // Both the key and the mirrored key of each entry
+    index: HashMap<String, usize>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    // Finds the tables in a list of directories separated like the PATH environment variable
+    pub fn new(path: &str) -> Self {
+        let mut wdl_files = Vec::new();
+        let mut dtz_files = HashMap::new();
+        for directory in std::env::split_paths(path) {
+            let Ok(files) = fs::read_dir(&directory) else { continue };
+            for file in files.flatten() {
+                let path = file.path();
+                let Some(name) = path.file_stem().and_then(|name| name.to_str()).map(str::to_string) else { continue };
+                match path.extension().and_then(|extension| extension.to_str()) {
+                    Some("rtbw") => wdl_files.push((name, path)),
+                    Some("rtbz") => { dtz_files.entry(name).or_insert(path); },
+                    _ => (),
+                }
+            }
+        }
+
+        let mut tablebases = Tablebases::default();
+        for (name, wdl_path) in wdl_files {
+            let dtz_path = dtz_files.get(&name).cloned();
+            if let Some(entry) = TableEntry::new(&name, wdl_path, dtz_path) && !tablebases.index.contains_key(&entry.key) {
+                tablebases.max_pieces = tablebases.max_pieces.max(entry.piece_count);
+                tablebases.index.insert(entry.key.clone(), tablebases.entries.len());
+                tablebases.index.insert(mirrored_key(&entry.key), tablebases.entries.len());
+                tablebases.entries.push(entry);
+            }
+        }
+        tablebases
+    }
+
+    // Number of WDL tables found
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // Outcome of the position for the side to move, None if a table is missing
+    pub fn probe_wdl(&self, board: &mut Board) -> Option<Wdl> {
+        if board.castling_rights() != 0 {
+            return None;
+        }
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    // Distance to zeroing of the position in plies, positive when winning and negative when losing
+    // A cursed win or a blessed loss is 100 plies further than the actual distance, 0 is a draw
+    // The distance can be one ply off when the table doesn't store the side to move
+    pub fn probe_dtz(&self, board: &mut Board) -> Option<i32> {
+        if board.castling_rights() != 0 {
+            return None;
+        }
+
+        let (wdl, zeroing_best_move) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        // The best move is a capture or a pawn move that keeps the outcome
+        if zeroing_best_move {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        match self.probe_table(board, TableType::Dtz, wdl)? {
+            TableProbe::Value(dtz) => {
+                let cursed = if matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss) { 100 } else { 0 };
+                Some((dtz + cursed) * wdl.signum())
+            },
+            // One more ply, the best move is a non zeroing one since the zeroing moves have already been searched
+            TableProbe::WrongSideToMove => {
+                let mut min_dtz = i32::MAX;
+                for m in board.legal_move_gen() {
+                    let zeroing = is_zeroing(board, m);
+                    let ext_move = board.make(m);
+                    let dtz = if zeroing {
+                        self.search(board, false).map(|(wdl, _)| -dtz_before_zeroing(wdl))
+                    } else {
+                        self.probe_dtz(board).map(|dtz| -dtz)
+                    };
+                    let checkmate = dtz == Some(1) && board.in_check() && board.legal_move_gen().is_empty();
+                    board.unmake(ext_move);
+
+                    let mut dtz = dtz?;
+                    if checkmate {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                        min_dtz = dtz;
+                    }
+                }
+                // No legal move, we are mated
+                Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+            },
+        }
+    }
+
+    // Legal moves keeping the best outcome of the root position, ranked by their distance to zeroing
+    // so that wins are only counted when they can be converted before the fifty-move rule
+    // None if the position is not in the tables
+    pub fn root_moves(&self, board: &mut Board) -> Option<Vec<Move>> {
+        if board.castling_rights() != 0 || board.occupancy().count_ones() as usize > self.max_pieces {
+            return None;
+        }
+
+        let halfmove_clock = board.halfmove_clock() as i32;
+        // A repetition means that the opponent can force a draw if we don't make progress
+        let repetition = board.is_repetition();
+        let mut ranked_moves = Vec::new();
+        for m in board.legal_move_gen() {
+            let ext_move = board.make(m);
+            let dtz = if board.halfmove_clock() == 0 {
+                self.probe_wdl(board).map(|wdl| dtz_before_zeroing(-wdl))
+            } else {
+                self.probe_dtz(board).map(|dtz| -dtz + (-dtz).signum())
+            };
+            let checkmate = board.in_check() && board.legal_move_gen().is_empty();
+            board.unmake(ext_move);
+
+            let dtz = if checkmate { 1 } else { dtz? };
+            let rank = match dtz.cmp(&0) {
+                Ordering::Greater if dtz + halfmove_clock <= 99 && !repetition => MAX_DTZ,
+                Ordering::Greater => MAX_DTZ - (dtz + halfmove_clock),
+                Ordering::Less if -dtz * 2 + halfmove_clock < 100 => -MAX_DTZ,
+                Ordering::Less => -MAX_DTZ + (-dtz + halfmove_clock),
+                Ordering::Equal => 0,
+            };
+            ranked_moves.push((m, rank));
+        }
+
+        let best_rank = ranked_moves.iter().map(|&(_, rank)| rank).max()?;
+        Some(ranked_moves.into_iter().filter(|&(_, rank)| rank == best_rank).map(|(m, _)| m).collect())
+    }
+
+    // Outcome of the position with the captures (and the pawn moves for DTZ) searched first, as they
+    // are not always stored correctly, and whether the best move is one of them
+    fn search(&self, board: &mut Board, check_zeroing_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = board.legal_move_gen();
+        let mut best = Wdl::Loss;
+        let mut move_count = 0;
+        for &m in moves.iter() {
+            if !is_capture(m) && (!check_zeroing_moves || board.squares[m.from() as usize] != Some(PAWN)) {
+                continue;
+            }
+            move_count += 1;
+
+            let ext_move = board.make(m);
+            let wdl = self.search(board, false).map(|(wdl, _)| -wdl);
+            board.unmake(ext_move);
+
+            let wdl = wdl?;
+            if wdl > best {
+                best = wdl;
+                if wdl >= Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+
+        // Every legal move has been searched, the tables are not needed
+        let no_more_moves = move_count > 0 && move_count == moves.len();
+        let wdl = if no_more_moves {
+            best
+        } else {
+            match self.probe_table(board, TableType::Wdl, Wdl::Draw)? {
+                TableProbe::Value(value) => Wdl::from_value(value)?,
+                TableProbe::WrongSideToMove => return None,
+            }
+        };
+
+        if best >= wdl {
+            Some((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Some((wdl, false))
+        }
+    }
+
+    // Raw value stored in the table for the position, wdl is the outcome of the position for DTZ tables
+    fn probe_table(&self, board: &Board, table_type: TableType, wdl: Wdl) -> Option<TableProbe> {
+        if board.occupancy().count_ones() == 2 {
+            return Some(TableProbe::Value(0));
+        }
+
+        let material_key = format!("{}v{}", material(board, WHITE), material(board, BLACK));
+        let entry = &self.entries[*self.index.get(&material_key)?];
+        let table = entry.table(table_type)?;
+
+        let (side_to_move, file, index) = encode(entry, table, table_type, board, &material_key)?;
+        let data = table.pairs(table_type, side_to_move, file);
+        if table_type == TableType::Dtz && (data.flags & STM_FLAG) as usize != side_to_move && (entry.has_pawns || !entry.symmetric) {
+            return Some(TableProbe::WrongSideToMove);
+        }
+
+        let value = decompress_pairs(&table.bytes, data, index)?;
+        Some(TableProbe::Value(table.map_score(table_type, file, value, wdl)?))
+    }
+}
+
+// Side to move and file of the leading pawn selecting the section of the table storing the position,
+// and index of the position in it. The material key of the board must be the key or the mirrored key of the entry
+fn encode(entry: &TableEntry, table: &TableData, table_type: TableType, board: &Board, material_key: &str) -> Option<(usize, usize, u64)> {
+    // Tables are stored with the stronger side as white, and symmetric ones with white to move
+    let flip = (entry.symmetric && board.to_move == BLACK) || material_key != entry.key;
+    let flip_color = if flip { 8 } else { 0 };
+    let flip_squares = if flip { 56 } else { 0 };
+    let side_to_move = (flip != (board.to_move == BLACK)) as usize;
+
+    let encoding = &*ENCODING;
+    let mut squares = [0; MAX_PIECES];
+    let mut pieces = [0; MAX_PIECES];
+    let mut size = 0;
+    let mut lead_pawns = EMPTY;
+    let mut file = 0;
+
+    // The leading pawns are encoded first, and the table of the file of the most advanced one
+    // towards the center is used
+    if entry.has_pawns {
+        let lead_color = if (table.items[0][0].pieces[0] ^ flip_color) & 8 != 0 { BLACK } else { WHITE };
+        lead_pawns = board.bitboards[PAWN] & board.pieces[lead_color];
+        for sq in BitIter::from(lead_pawns) {
+            squares[size] = sq ^ flip_squares;
+            size += 1;
+        }
+        let lead = (0..size).max_by_key(|&i| encoding.map_pawns[squares[i]])?;
+        squares.swap(0, lead);
+        file = (squares[0] % 8).min(7 - squares[0] % 8);
+    }
+    let lead_pawns_count = size;
+
+    let data = table.pairs(table_type, side_to_move, file);
+    for sq in BitIter::from(board.occupancy() & !lead_pawns) {
+        squares[size] = sq ^ flip_squares;
+        pieces[size] = tb_piece(board, sq) ^ flip_color;
+        size += 1;
+    }
+
+    // Same order as the pieces of the table
+    for i in lead_pawns_count..size.saturating_sub(1) {
+        if let Some(j) = (i+1..size).find(|&j| data.pieces[i] == pieces[j]) {
+            pieces.swap(i, j);
+            squares.swap(i, j);
+        }
+    }
+
+    // Mirrored so that the first piece is on the a-d files
+    if squares[0] % 8 > 3 {
+        squares[..size].iter_mut().for_each(|sq| *sq ^= 7);
+    }
+
+    let mut index;
+    if entry.has_pawns {
+        index = encoding.lead_pawn_idx[lead_pawns_count][squares[0]];
+        squares[1..lead_pawns_count].sort_by_key(|&sq| encoding.map_pawns[sq]);
+        for (i, &sq) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+            index += encoding.binomial[i][encoding.map_pawns[sq]];
+        }
+    } else {
+        // Without pawns the position is also mirrored to the first ranks and below the a1-h8 diagonal
+        if squares[0] / 8 > 3 {
+            squares[..size].iter_mut().for_each(|sq| *sq ^= 56);
+        }
+        for i in 0..data.group_len[0] {
+            let off = off_diagonal(squares[i]);
+            if off == 0 {
+                continue;
+            }
+            if off > 0 {
+                squares[i..size].iter_mut().for_each(|sq| *sq = ((*sq >> 3) | (*sq << 3)) & 63);
+            }
+            break;
+        }
+
+        index = if entry.has_unique_pieces {
+            let [first, second, third] = [squares[0], squares[1], squares[2]];
+            let adjust1 = (second > first) as usize;
+            let adjust2 = (third > first) as usize + (third > second) as usize;
+            (if off_diagonal(first) != 0 {
+                (encoding.map_a1d1d4[first] * 63 + (second - adjust1)) * 62 + third - adjust2
+            } else if off_diagonal(second) != 0 {
+                (6 * 63 + (first / 8) * 28 + encoding.map_b1h1h7[second]) * 62 + third - adjust2
+            } else if off_diagonal(third) != 0 {
+                6 * 63 * 62 + 4 * 28 * 62 + (first / 8) * 7 * 28 + (second / 8 - adjust1) * 28 + encoding.map_b1h1h7[third]
+            } else {
+                6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + (first / 8) * 7 * 6 + (second / 8 - adjust1) * 6 + (third / 8 - adjust2)
+            }) as u64
+        } else {
+            encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]]
+        };
+    }
+
+    // The other groups of identical pieces are encoded by the combination of their squares
+    // among the ones left free by the previous groups
+    index *= data.group_idx[0];
+    let mut group_start = data.group_len[0];
+    let mut remaining_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+    let mut next = 1;
+    while data.group_len[next] != 0 {
+        let group_end = group_start + data.group_len[next];
+        squares[group_start..group_end].sort_unstable();
+        let mut group_index = 0;
+        for (i, &sq) in squares[group_start..group_end].iter().enumerate() {
+            let adjust = squares[..group_start].iter().filter(|&&previous| sq > previous).count();
+            group_index += encoding.binomial[i+1][sq - adjust - if remaining_pawns { 8 } else { 0 }];
+        }
+        remaining_pawns = false;
+        index += group_index * data.group_idx[next];
+        group_start = group_end;
+        next += 1;
+    }
+
+    Some((side_to_move, file, index))
+}
+
+impl TableEntry {
+    // None if the name is not a valid material configuration, e.g. KRPvKR
+    fn new(name: &str, wdl_path: PathBuf, dtz_path: Option<PathBuf>) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0; 6]; 2];
+        for (side, material) in [white, black].into_iter().enumerate() {
+            if !material.starts_with('K') || material[1..].contains('K') {
+                return None;
+            }
+            for piece in material.chars() {
+                counts[side][piece_ordinal(piece)?] += 1;
+            }
+        }
+
+        let piece_count: usize = counts.iter().flatten().sum();
+        if piece_count > MAX_PIECES {
+            return None;
+        }
+        let pawns = [counts[0][usize::from(PAWN)], counts[1][usize::from(PAWN)]];
+        // The leading color is the one with the fewest pawns
+        let pawn_count = if pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]) { pawns } else { [pawns[1], pawns[0]] };
+        let key = format!("{}v{}", material_name(&counts[0]), material_name(&counts[1]));
+
+        Some(TableEntry {
+            symmetric: key == mirrored_key(&key),
+            key,
+            piece_count,
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces: counts.iter().any(|side| side[..usize::from(KING)].contains(&1)),
+            pawn_count,
+            wdl_path,
+            dtz_path,
+            wdl: OnceLock::new(),
+            dtz: OnceLock::new(),
+        })
+    }
+
+    // Read on first use, None if the file is missing or invalid
+    fn table(&self, table_type: TableType) -> Option<&TableData> {
+        match table_type {
+            TableType::Wdl => self.wdl.get_or_init(|| TableData::load(&self.wdl_path, self, table_type)),
+            TableType::Dtz => self.dtz.get_or_init(|| TableData::load(self.dtz_path.as_ref()?, self, table_type)),
+        }.as_ref()
+    }
+}
+
+impl TableData {
+    fn load(path: &Path, entry: &TableEntry, table_type: TableType) -> Option<Self> {
+        Self::parse(fs::read(path).ok()?, entry, table_type)
+    }
+
+    fn parse(bytes: Vec<u8>, entry: &TableEntry, table_type: TableType) -> Option<Self> {
+        let magic = if table_type == TableType::Wdl { WDL_MAGIC } else { DTZ_MAGIC };
+        if bytes.get(..4)? != magic {
+            return None;
+        }
+        let flags = *bytes.get(4)?;
+        if (flags & HAS_PAWNS_FLAG != 0) != entry.has_pawns || (flags & SPLIT_FLAG != 0) == entry.symmetric {
+            return None;
+        }
+
+        // WDL tables store both sides to move unless they are symmetric
+        let sides = if table_type == TableType::Wdl && !entry.symmetric { 2 } else { 1 };
+        let files = if entry.has_pawns { 4 } else { 1 };
+        let both_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+        let mut table = TableData { bytes, items: Default::default(), map: 0 };
+        let bytes = &table.bytes;
+
+        let mut offset = 5;
+        for file in 0..files {
+            // Encoding order of the groups of the leading pawns and of the other pawns, 0xF if none
+            let order = [
+                [bytes.get(offset)? & 0xF, if both_pawns { bytes.get(offset + 1)? & 0xF } else { 0xF }],
+                [bytes.get(offset)? >> 4, if both_pawns { bytes.get(offset + 1)? >> 4 } else { 0xF }],
+            ];
+            offset += 1 + both_pawns as usize;
+
+            for k in 0..entry.piece_count {
+                let piece = *bytes.get(offset)?;
+                table.items[0][file].pieces[k] = piece & 0xF;
+                table.items[1][file].pieces[k] = piece >> 4;
+                offset += 1;
+            }
+            for (side, order) in order.into_iter().enumerate().take(sides) {
+                table.items[side][file].set_groups(entry, order, file);
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = table.items[side][file].set_sizes(bytes, offset)?;
+            }
+        }
+
+        if table_type == TableType::Dtz {
+            table.map = offset;
+            for file in 0..files {
+                let data = &mut table.items[0][file];
+                if data.flags & MAPPED_FLAG == 0 {
+                    continue;
+                }
+                if data.flags & WIDE_FLAG != 0 {
+                    offset += offset & 1;
+                    for i in 0..4 {
+                        data.map_idx[i] = (offset - table.map) / 2 + 1;
+                        offset += 2 * read_u16(bytes, offset)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        data.map_idx[i] = offset - table.map + 1;
+                        offset += *bytes.get(offset)? as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                table.items[side][file].sparse_index = offset;
+                offset += table.items[side][file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                table.items[side][file].block_length = offset;
+                offset += table.items[side][file].block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                // Blocks are aligned on 64 bytes
+                offset = (offset + 0x3F) & !0x3F;
+                table.items[side][file].data = offset;
+                offset += table.items[side][file].num_blocks * table.items[side][file].block_size;
+            }
+        }
+
+        (offset <= bytes.len()).then_some(table)
+    }
+
+    fn pairs(&self, table_type: TableType, side_to_move: usize, file: usize) -> &PairsData {
+        let side = if table_type == TableType::Wdl { side_to_move } else { 0 };
+        &self.items[side][file]
+    }
+
+    fn map_score(&self, table_type: TableType, file: usize, value: i32, wdl: Wdl) -> Option<i32> {
+        if table_type == TableType::Wdl {
+            return Some(value - 2);
+        }
+
+        let data = &self.items[0][file];
+        let mut value = value;
+        if data.flags & MAPPED_FLAG != 0 {
+            let map_index = match wdl {
+                Wdl::Win => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+                Wdl::Draw => 0,
+            };
+            let index = data.map_idx[map_index] + value as usize;
+            value = if data.flags & WIDE_FLAG != 0 {
+                read_u16(&self.bytes, self.map + 2 * index)? as i32
+            } else {
+                *self.bytes.get(self.map + index)? as i32
+            };
+        }
+
+        // Distances are stored in moves rather than plies when they can't be odd
+        if (wdl == Wdl::Win && data.flags & WIN_PLIES_FLAG == 0) || (wdl == Wdl::Loss && data.flags & LOSS_PLIES_FLAG == 0)
+            || wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+}
+
+impl PairsData {
+    // Groups consecutive identical pieces, the first group being the leading pawns or the kings with
+    // the unique pieces, and computes the index multiplier of each group from the encoding order
+    fn set_groups(&mut self, entry: &TableEntry, order: [u8; 2], file: usize) {
+        let encoding = &*ENCODING;
+        let mut n = 0;
+        let mut first_len: i32 = if entry.has_pawns { 0 } else if entry.has_unique_pieces { 3 } else { 2 };
+        self.group_len[0] = 1;
+        for i in 1..entry.piece_count {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i-1] {
+                self.group_len[n] += 1;
+            } else {
+                n += 1;
+                self.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        self.group_len[n] = 0;
+
+        let both_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - self.group_len[0] - if both_pawns { self.group_len[1] } else { 0 };
+        let mut index = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                self.group_idx[0] = index;
+                index *= if entry.has_pawns {
+                    encoding.lead_pawns_size[self.group_len[0]][file]
+                } else if entry.has_unique_pieces {
+                    UNIQUE_PIECES_SIZE
+                } else {
+                    KINGS_SIZE
+                };
+            } else if k == order[1] {
+                self.group_idx[1] = index;
+                index *= encoding.binomial[self.group_len[1]][48 - self.group_len[0]];
+            } else {
+                self.group_idx[next] = index;
+                index *= encoding.binomial[self.group_len[next]][free_squares];
+                free_squares -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[n] = index;
+    }
+
+    // Reads the compression parameters, returns the offset after them
+    fn set_sizes(&mut self, bytes: &[u8], mut offset: usize) -> Option<usize> {
+        self.flags = *bytes.get(offset)?;
+        offset += 1;
+
+        if self.flags & SINGLE_VALUE_FLAG != 0 {
+            self.num_blocks = 0;
+            self.block_length_size = 0;
+            self.span = 0;
+            self.sparse_index_size = 0;
+            self.min_sym_len = *bytes.get(offset)? as usize;
+            return Some(offset + 1);
+        }
+
+        let groups = self.group_len.iter().position(|&len| len == 0)?;
+        let table_size = self.group_idx[groups] as usize;
+        self.block_size = 1 << *bytes.get(offset)?;
+        self.span = 1 << *bytes.get(offset + 1)?;
+        self.sparse_index_size = table_size.div_ceil(self.span);
+        let padding = *bytes.get(offset + 2)? as usize;
+        self.num_blocks = read_u32(bytes, offset + 3)? as usize;
+        self.block_length_size = self.num_blocks + padding;
+        self.max_sym_len = *bytes.get(offset + 7)? as usize;
+        self.min_sym_len = *bytes.get(offset + 8)? as usize;
+        self.lowest_sym = offset + 9;
+        if self.max_sym_len < self.min_sym_len || self.min_sym_len == 0 || self.max_sym_len > 64 {
+            return None;
+        }
+
+        // Canonical Huffman code, the first code of each length is derived from the shorter ones
+        let lengths = self.max_sym_len - self.min_sym_len + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = read_u16(bytes, self.lowest_sym + 2 * i)? as u64;
+            let next_lowest = read_u16(bytes, self.lowest_sym + 2 * (i + 1))? as u64;
+            self.base64[i] = self.base64[i+1].wrapping_add(lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base.checked_shl((64 - i - self.min_sym_len) as u32).unwrap_or(0);
+        }
+
+        offset = self.lowest_sym + 2 * lengths;
+        let symbols = read_u16(bytes, offset)? as usize;
+        offset += 2;
+        self.btree = offset;
+        if bytes.len() < self.btree + 3 * symbols {
+            return None;
+        }
+
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symlen[symbol] = self.set_symlen(bytes, symbol, &mut visited)?;
+            }
+        }
+        Some(offset + 3 * symbols + (symbols & 1))
+    }
+
+    fn set_symlen(&mut self, bytes: &[u8], symbol: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[symbol] = true;
+        let (left, right) = self.children(bytes, symbol)?;
+        // A leaf
+        if right == 0xFFF {
+            return Some(0);
+        }
+        if left >= self.symlen.len() || right >= self.symlen.len() {
+            return None;
+        }
+        if !visited[left] {
+            self.symlen[left] = self.set_symlen(bytes, left, visited)?;
+        }
+        if !visited[right] {
+            self.symlen[right] = self.set_symlen(bytes, right, visited)?;
+        }
+        Some(self.symlen[left].wrapping_add(self.symlen[right]).wrapping_add(1))
+    }
+
+    // The two symbols a symbol expands to, packed on 3 bytes, or the value of a leaf as the left one
+    fn children(&self, bytes: &[u8], symbol: usize) -> Option<(usize, usize)> {
+        let node = bytes.get(self.btree + 3 * symbol..self.btree + 3 * symbol + 3)?;
+        let left = ((node[1] as usize & 0xF) << 8) | node[0] as usize;
+        let right = ((node[2] as usize) << 4) | (node[1] as usize >> 4);
+        Some((left, right))
+    }
+}
+
+// Value at index in a compressed section
+fn decompress_pairs(bytes: &[u8], data: &PairsData, index: u64) -> Option<i32> {
+    if data.flags & SINGLE_VALUE_FLAG != 0 {
+        return Some(data.min_sym_len as i32);
+    }
+
+    // The sparse index gives the block and the offset in the block of the middle of each span
+    let index = index as usize;
+    let sparse_entry = data.sparse_index + 6 * (index / data.span);
+    let mut block = read_u32(bytes, sparse_entry)? as usize;
+    let mut offset = read_u16(bytes, sparse_entry + 4)? as i64 + (index % data.span) as i64 - (data.span / 2) as i64;
+
+    let block_length = |block: usize| read_u16(bytes, data.block_length + 2 * block).map(|length| length as i64);
+    while offset < 0 {
+        block = block.checked_sub(1)?;
+        offset += block_length(block)? + 1;
+    }
+    while offset > block_length(block)? {
+        offset -= block_length(block)? + 1;
+        block += 1;
+    }
+
+    // Codes are read from a 64 bits buffer refilled 32 bits at a time
+    let mut position = data.data + block * data.block_size;
+    let mut buffer = read_u64_be(bytes, position)?;
+    position += 8;
+    let mut buffer_size = 64;
+    let mut symbol;
+    loop {
+        let mut length = 0;
+        while buffer < data.base64[length] {
+            length += 1;
+            if length >= data.base64.len() {
+                return None;
+            }
+        }
+        symbol = ((buffer - data.base64[length]) >> (64 - length - data.min_sym_len)) as usize;
+        symbol += read_u16(bytes, data.lowest_sym + 2 * length)? as usize;
+
+        let symbol_length = *data.symlen.get(symbol)? as i64;
+        if offset < symbol_length + 1 {
+            break;
+        }
+        offset -= symbol_length + 1;
+        length += data.min_sym_len;
+        buffer = buffer.checked_shl(length as u32).unwrap_or(0);
+        buffer_size -= length;
+        if buffer_size <= 32 {
+            buffer_size += 32;
+            // The last block can end with the file, what is read past it is never decoded
+            buffer |= (read_u32_be(bytes, position).unwrap_or(0) as u64) << (64 - buffer_size);
+            position += 4;
+        }
+    }
+
+    // Walks down the pairs to the value at offset
+    while data.symlen[symbol] != 0 {
+        let (left, right) = data.children(bytes, symbol)?;
+        let left_length = *data.symlen.get(left)? as i64;
+        if offset < left_length + 1 {
+            symbol = left;
+        } else {
+            offset -= left_length + 1;
+            symbol = right;
+        }
+        data.symlen.get(symbol)?;
+    }
+    Some(data.children(bytes, symbol)?.0 as i32)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64_be(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+fn is_capture(m: Move) -> bool {
+    matches!(m.infos(), MoveInfo::Capture | MoveInfo::EnPassantCapture | MoveInfo::CapturePromotion(_))
+}
+
+// Captures and pawn moves reset the fifty-move counter
+fn is_zeroing(board: &Board, m: Move) -> bool {
+    is_capture(m) || board.squares[m.from() as usize] == Some(PAWN)
+}
+
+// Distance to zeroing of a position whose best move is a zeroing one
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+    }
+}
+
+// Code of the piece on a square in the table files
+fn tb_piece(board: &Board, sq: usize) -> u8 {
+    let piece = board.squares[sq].expect("occupied square without a piece");
+    let color = if board.pieces[BLACK].has(sq as Square) { 8 } else { 0 };
+    color | TB_PIECE_TYPES[usize::from(piece)]
+}
+
+// Pieces of a color as in the table names, e.g. KRP
+fn material(board: &Board, color: Color) -> String {
+    let mut counts = [0; 6];
+    for piece in [QUEEN, ROOK, BISHOP, KNIGHT, PAWN, KING] {
+        counts[usize::from(piece)] = (board.bitboards[piece] & board.pieces[color]).count_ones() as usize;
+    }
+    material_name(&counts)
+}
+
+fn material_name(counts: &[usize; 6]) -> String {
+    let mut name = "K".repeat(counts[usize::from(KING)]);
+    for piece in [QUEEN, ROOK, BISHOP, KNIGHT, PAWN] {
+        name.extend(std::iter::repeat_n(char::from(piece).to_ascii_uppercase(), counts[usize::from(piece)]));
+    }
+    name
+}
+
+fn mirrored_key(key: &str) -> String {
+    let (white, black) = key.split_once('v').unwrap_or((key, ""));
+    format!("{black}v{white}")
+}
+
+fn piece_ordinal(piece: char) -> Option<usize> {
+    let piece = match piece {
+        'K' => KING,
+        'Q' => QUEEN,
+        'R' => ROOK,
+        'B' => BISHOP,
+        'N' => KNIGHT,
+        'P' => PAWN,
+        _ => return None,
+    };
+    Some(usize::from(piece))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding() {
+        let encoding = &*ENCODING;
+        // Every legal placement of the kings has a distinct code
+        let mut kings: Vec<u64> = (0..=27).filter(|&sq| sq == 1 || encoding.map_a1d1d4[sq] != 0)
+            .flat_map(|first| (0..64).filter(move |&second| !(KING_ATTACK[first] | (first as Square).as_bitboard()).has(second as Square))
+                .filter(move |&second| off_diagonal(first) != 0 || off_diagonal(second) <= 0)
+                .map(move |second| (first, second)))
+            .map(|(first, second)| encoding.map_kk[encoding.map_a1d1d4[first]][second])
+            .collect();
+        kings.sort_unstable();
+        kings.dedup();
+        assert_eq!(kings, (0..KINGS_SIZE).collect::<Vec<_>>());
+
+        let mut pawns: Vec<usize> = (8..56).map(|sq| encoding.map_pawns[sq]).collect();
+        pawns.sort_unstable();
+        assert_eq!(pawns, (0..48).collect::<Vec<_>>());
+        assert_eq!(encoding.lead_pawns_size[1], [6; 4]);
+        assert_eq!(encoding.binomial[2][5], 10);
+        assert_eq!(encoding.binomial[5][48], 1712304);
+    }
+
+    #[test]
+    fn test_table_names() {
+        let entry = TableEntry::new("KRPvKR", PathBuf::new(), None).unwrap();
+        assert_eq!((entry.key.as_str(), entry.piece_count, entry.has_pawns, entry.pawn_count), ("KRPvKR", 5, true, [1, 0]));
+        assert!(!entry.symmetric);
+        let entry = TableEntry::new("KPvKP", PathBuf::new(), None).unwrap();
+        assert!(entry.symmetric && entry.has_unique_pieces);
+        let entry = TableEntry::new("KNNvK", PathBuf::new(), None).unwrap();
+        assert!(!entry.has_pawns && !entry.has_unique_pieces);
+        for name in ["KQK", "QvK", "KXvK", "KKvK", "KQQQQvKQQ"] {
+            assert!(TableEntry::new(name, PathBuf::new(), None).is_none(), "{name}");
+        }
+
+        let board = Board::from_fen("8/8/4k3/8/3r4/8/1P2KR2/8 w - - 0 1").unwrap();
+        assert_eq!(format!("{}v{}", material(&board, WHITE), material(&board, BLACK)), "KRPvKR");
+    }
+
+    #[test]
+    fn test_probe_without_tables() {
+        let tablebases = Tablebases::new("/nonexistent/syzygy");
+        assert_eq!((tablebases.len(), tablebases.max_pieces()), (0, 0));
+
+        // The outcome is known without tables when every move has been searched
+        let mut board = Board::from_fen("8/8/8/8/8/3k4/8/3K4 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut board), Some(Wdl::Draw));
+        let mut board = Board::from_fen("k7/1Q6/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut board), Some(Wdl::Draw));
+        assert_eq!(tablebases.probe_dtz(&mut board), Some(0));
+
+        let mut board = Board::from_fen("k7/8/1Q6/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut board), None);
+        assert_eq!(tablebases.root_moves(&mut board), None);
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut board), None);
+    }
+
+    // Rewrites the fixture tables, every position being probed once the tables are written
+    #[test]
+    #[ignore = "rewrites the fixture tables, run it in release"]
+    fn generate_fixtures() {
+        generate::write_tables(Path::new(FIXTURES));
+    }
+
+    #[test]
+    fn test_probe_tables() {
+        let tablebases = Tablebases::new(FIXTURES);
+        assert_eq!((tablebases.len(), tablebases.max_pieces()), (7, 4));
+        check_known_results(&tablebases);
+    }
+
+    // The same results from the official tables, that need the KQvKP to KNvKP ones for the promotions
+    #[test]
+    #[ignore = "needs the official tables up to 4 pieces in SYZYGY_PATH"]
+    fn test_probe_official_tables() {
+        let path = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH is not set");
+        let tablebases = Tablebases::new(&path);
+        assert!(tablebases.max_pieces() >= 4);
+        check_known_results(&tablebases);
+    }
+
+    fn check_known_results(tablebases: &Tablebases) {
+        for (fen, wdl) in [
+            ("4k3/8/8/8/8/8/8/4K2Q w - - 0 1", Wdl::Win),
+            ("4k3/8/8/8/8/8/8/4K2Q b - - 0 1", Wdl::Loss),
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+            // Mirrored, black has the pawn
+            ("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1", Wdl::Win),
+            // The black king stands in front of the pawn
+            ("8/8/8/8/4k3/8/4P3/4K3 w - - 0 1", Wdl::Draw),
+            // Stalemate
+            ("k7/8/1Q6/8/8/8/8/K7 b - - 0 1", Wdl::Draw),
+            // The rook is hanging
+            ("8/8/8/8/8/8/6kR/K7 b - - 0 1", Wdl::Draw),
+            ("4k3/8/8/8/8/8/8/2B1K3 b - - 0 1", Wdl::Draw),
+            ("4k3/8/8/8/8/8/8/3NK3 w - - 0 1", Wdl::Draw),
+            // Two knights only win when the mate is on the board
+            ("7k/8/5NK1/4N3/8/8/8/8 w - - 0 1", Wdl::Win),
+            ("7k/8/5NK1/4N3/8/8/8/8 b - - 0 1", Wdl::Draw),
+            ("7k/4NN2/6K1/8/8/8/8/8 b - - 0 1", Wdl::Loss),
+            ("4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1", Wdl::Draw),
+            // Promotions with mate, then stalemates with both pawns blocked
+            ("k7/2P5/K7/8/8/8/7p/8 w - - 0 1", Wdl::Win),
+            ("7k/5P2/7K/8/8/8/p7/8 w - - 0 1", Wdl::Win),
+            ("8/7P/8/8/8/k7/2p5/K7 b - - 0 1", Wdl::Win),
+            ("k7/Pp6/1K6/8/8/8/8/8 b - - 0 1", Wdl::Draw),
+            ("7k/6pP/6K1/8/8/8/8/8 b - - 0 1", Wdl::Draw),
+            // The white pawn promotes with check long before the black one
+            ("7k/8/2K4p/1P6/8/8/8/8 w - - 0 1", Wdl::Win),
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            assert_eq!(tablebases.probe_wdl(&mut board), Some(wdl), "{fen}");
+        }
+
+        for (fen, dtz) in [
+            // Mate in one, then mated
+            ("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1", 1),
+            ("Q6k/8/6K1/8/8/8/8/8 b - - 0 1", -1),
+            ("7k/8/6K1/8/8/8/8/R7 w - - 0 1", 1),
+            // Mate in two with Kg6 and Ra8, white to move is not stored in the KRvK DTZ table
+            ("7k/8/5K2/8/8/8/8/R7 w - - 0 1", 3),
+            ("7k/8/6K1/8/8/8/8/R7 b - - 0 1", -2),
+            // The pawn is pushed after a king move
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", 3),
+            ("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1", 3),
+            // Promotion
+            ("8/4P3/8/8/8/2k5/8/4K3 w - - 0 1", 1),
+            ("8/8/8/8/4k3/8/4P3/4K3 w - - 0 1", 0),
+            // Black to move is not stored in the KNNvK DTZ table
+            ("7k/8/5NK1/4N3/8/8/8/8 w - - 0 1", 1),
+            ("7k/4NN2/6K1/8/8/8/8/8 b - - 0 1", -1),
+            ("4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1", 0),
+            ("7k/8/2K4p/1P6/8/8/8/8 w - - 0 1", 1),
+            ("k7/Pp6/1K6/8/8/8/8/8 b - - 0 1", 0),
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            assert_eq!(tablebases.probe_dtz(&mut board), Some(dtz), "{fen}");
+        }
+
+        // Only the mate keeps the win before the fifty-move rule
+        let mut board = Board::from_fen("7k/8/6K1/8/8/8/8/1Q6 w - - 98 80").unwrap();
+        let mut root_moves: Vec<String> = tablebases.root_moves(&mut board).unwrap().into_iter().map(Move::to_uci).collect();
+        root_moves.sort();
+        assert_eq!(root_moves, ["b1b8"]);
+
+        let mut board = Board::from_fen("7k/8/5NK1/4N3/8/8/8/8 w - - 0 1").unwrap();
+        let root_moves: Vec<String> = tablebases.root_moves(&mut board).unwrap().into_iter().map(Move::to_uci).collect();
+        assert_eq!(root_moves, ["e5f7"]);
+
+        // Promoting to a knight draws
+        let mut board = Board::from_fen("8/4P3/8/8/8/2k5/8/4K3 w - - 0 1").unwrap();
+        let root_moves: Vec<String> = tablebases.root_moves(&mut board).unwrap().into_iter().map(Move::to_uci).collect();
+        assert!(root_moves.contains(&"e7e8q".to_string()) && root_moves.contains(&"e7e8r".to_string()));
+        assert!(!root_moves.contains(&"e7e8n".to_string()) && !root_moves.contains(&"e7e8b".to_string()));
+    }
+}
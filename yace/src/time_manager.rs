@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 10;
+pub const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+
+// Number of moves the remaining time is split over when movestogo is not given
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+// Clock state for the side to move, all times are in milliseconds
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TimeControl {
+    pub time: Option<u64>,
+    pub increment: u64,
+    pub moves_to_go: Option<u64>,
+    pub movetime: Option<u64>,
+}
+
+// The soft limit is checked between iterations, a new depth is not started once it is exceeded
+// The hard limit aborts the search in the middle of an iteration
+#[derive(Debug, Clone, Copy)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+}
+
+impl TimeManager {
+    pub fn new(start: Instant, control: TimeControl, move_overhead: u64) -> Self {
+        let (soft_limit, hard_limit) = if let Some(movetime) = control.movetime {
+            let limit = movetime.saturating_sub(move_overhead).max(1);
+            (Some(limit), Some(limit))
+        } else if let Some(time) = control.time {
+            // The overhead is lost on every move, keep it out of the usable time
+            let available = time.saturating_sub(move_overhead).max(1);
+            let moves_to_go = control.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+            let soft_limit = (available / moves_to_go + control.increment * 3 / 4).min(available / 2);
+            let hard_limit = (soft_limit * 4).min(available * 3 / 4);
+            (Some(soft_limit.max(1)), Some(hard_limit.max(1)))
+        } else {
+            (None, None)
+        };
+
+        TimeManager {
+            start,
+            soft_limit: soft_limit.map(Duration::from_millis),
+            hard_limit: hard_limit.map(Duration::from_millis),
+        }
+    }
+
+    // Only bounded by depth or by a stop command
+    pub fn infinite() -> Self {
+        TimeManager { start: Instant::now(), soft_limit: None, hard_limit: None }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard_limit.is_some_and(|limit| self.elapsed() >= limit)
+    }
+
+    // The next iteration usually takes longer than all the previous ones together
+    // so it is not started when it has little chance to complete
+    pub fn should_start_iteration(&self) -> bool {
+        self.soft_limit.is_none_or(|limit| self.elapsed() < limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(control: TimeControl, move_overhead: u64) -> (Option<Duration>, Option<Duration>) {
+        let time_manager = TimeManager::new(Instant::now(), control, move_overhead);
+        (time_manager.soft_limit, time_manager.hard_limit)
+    }
+
+    #[test]
+    fn test_limits() {
+        let control = TimeControl { time: Some(60010), increment: 1000, moves_to_go: Some(10), movetime: None };
+        assert_eq!(limits(control, 10), (Some(Duration::from_millis(6750)), Some(Duration::from_millis(27000))));
+
+        // Sudden death with very little time left never uses more than half of it
+        let control = TimeControl { time: Some(110), increment: 0, moves_to_go: Some(1), movetime: None };
+        assert_eq!(limits(control, 10), (Some(Duration::from_millis(50)), Some(Duration::from_millis(75))));
+
+        // Flagging is better avoided even when the overhead is larger than the clock
+        let control = TimeControl { time: Some(5), ..Default::default() };
+        assert_eq!(limits(control, 10), (Some(Duration::from_millis(1)), Some(Duration::from_millis(1))));
+
+        let control = TimeControl { movetime: Some(100), ..Default::default() };
+        assert_eq!(limits(control, 10), (Some(Duration::from_millis(90)), Some(Duration::from_millis(90))));
+
+        assert_eq!(limits(TimeControl::default(), 10), (None, None));
+    }
+
+    #[test]
+    fn test_infinite() {
+        let time_manager = TimeManager::infinite();
+        assert!(time_manager.should_start_iteration());
+        assert!(!time_manager.hard_limit_reached());
+
+        let time_manager = TimeManager::new(Instant::now() - Duration::from_millis(200), TimeControl { movetime: Some(100), ..Default::default() }, 0);
+        assert!(!time_manager.should_start_iteration());
+        assert!(time_manager.hard_limit_reached());
+    }
+}
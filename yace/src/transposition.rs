@@ -0,0 +1,138 @@
+use crate::{board::*, search::TB_WIN_BOUND};
+
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+pub const MAX_HASH_SIZE_MB: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    Exact,
+    // The score is at least the stored one (beta cutoff)
+    Lower,
+    // The score is at most the stored one (no move raised alpha)
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TTEntry {
+    hash: ZobristHash,
+    pub best_move: Option<Move>,
+    score: i16,
+    pub depth: u8,
+    pub bound: Bound,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Option<TTEntry>>,
+}
+
+impl TTEntry {
+    // Mate and tablebase scores are stored relative to the node and converted back relative to the root
+    pub fn score(&self, ply: u8) -> i16 {
+        if self.score >= TB_WIN_BOUND {
+            self.score - ply as i16
+        } else if self.score <= -TB_WIN_BOUND {
+            self.score + ply as i16
+        } else {
+            self.score
+        }
+    }
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let entry_count = (size_mb.clamp(1, MAX_HASH_SIZE_MB) << 20) / size_of::<Option<TTEntry>>();
+        TranspositionTable { entries: vec![None; entry_count] }
+    }
+
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = Self::new(size_mb);
+    }
+
+    pub fn size_mb(&self) -> usize {
+        (self.entries.len() * size_of::<Option<TTEntry>>()) >> 20
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    // Maps the hash uniformly on the table without requiring a power of two size
+    fn index(&self, hash: ZobristHash) -> usize {
+        ((hash as u128 * self.entries.len() as u128) >> 64) as usize
+    }
+
+    pub fn probe(&self, hash: ZobristHash) -> Option<TTEntry> {
+        self.entries[self.index(hash)].filter(|entry| entry.hash == hash)
+    }
+
+    pub fn store(&mut self, hash: ZobristHash, best_move: Option<Move>, score: i16, depth: u8, bound: Bound, ply: u8) {
+        let index = self.index(hash);
+        let score = if score >= TB_WIN_BOUND {
+            score + ply as i16
+        } else if score <= -TB_WIN_BOUND {
+            score - ply as i16
+        } else {
+            score
+        };
+
+        // Deeper results of the same position are kept unless the new one is exact
+        if let Some(entry) = self.entries[index] && entry.hash == hash && entry.depth > depth && bound != Bound::Exact {
+            return;
+        }
+
+        // Keep the previous best move if we don't have one for this position
+        let best_move = best_move.or(self.entries[index].filter(|entry| entry.hash == hash).and_then(|entry| entry.best_move));
+
+        self.entries[index] = Some(TTEntry { hash, best_move, score, depth, bound });
+    }
+
+    // Permille of used entries, estimated on the first thousand entries
+    pub fn hashfull(&self) -> usize {
+        let sample = &self.entries[..self.entries.len().min(1000)];
+        sample.iter().filter(|entry| entry.is_some()).count() * 1000 / sample.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE_SCORE;
+
+    #[test]
+    fn test_probe_store() {
+        let mut tt = TranspositionTable::new(1);
+        let board = Board::new();
+        let m = board.parse_uci_move("e2e4").unwrap();
+
+        assert!(tt.probe(board.zobrist_hash).is_none());
+        tt.store(board.zobrist_hash, Some(m), 35, 4, Bound::Exact, 0);
+        let entry = tt.probe(board.zobrist_hash).unwrap();
+        assert_eq!(entry.best_move, Some(m));
+        assert_eq!(entry.score(0), 35);
+        assert_eq!(entry.depth, 4);
+        assert_eq!(entry.bound, Bound::Exact);
+
+        // Shallower non exact results don't replace deeper ones
+        tt.store(board.zobrist_hash, None, 10, 2, Bound::Lower, 0);
+        assert_eq!(tt.probe(board.zobrist_hash).unwrap().depth, 4);
+        tt.store(board.zobrist_hash, None, 10, 6, Bound::Lower, 0);
+        assert_eq!(tt.probe(board.zobrist_hash).unwrap().best_move, Some(m));
+
+        tt.clear();
+        assert!(tt.probe(board.zobrist_hash).is_none());
+        assert_eq!(tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn test_mate_score() {
+        let mut tt = TranspositionTable::new(1);
+
+        // Mate in 5 plies from the root found at ply 3 is a mate in 2 plies from the node
+        tt.store(42, None, MATE_SCORE - 5, 2, Bound::Exact, 3);
+        assert_eq!(tt.probe(42).unwrap().score(3), MATE_SCORE - 5);
+        assert_eq!(tt.probe(42).unwrap().score(1), MATE_SCORE - 3);
+
+        tt.store(42, None, -MATE_SCORE + 5, 2, Bound::Exact, 3);
+        assert_eq!(tt.probe(42).unwrap().score(1), -MATE_SCORE + 3);
+    }
+}
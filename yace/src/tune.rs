@@ -0,0 +1,252 @@
+// Texel tuning of the material values and square tables
+//
+// The positions file has one position per line: a fen followed by the game result from white point
+// of view, either 1-0, 0-1, 1/2-1/2 or 1.0, 0.0, 0.5, possibly within brackets or quotes
+// Positions are evaluated statically so they should be quiet
+// The tuned tables are written as Rust source that can replace the ones of evaluation.rs
+
+use std::{fs, io::{self, Write}};
+
+use crate::{board::*, evaluation::{evaluate, parameter_indices, parameters, Evaluator, MAX_PHASE, PARAMETERS_PER_PHASE, PARAMETER_COUNT}, pawn_structure::PawnHashTable};
+
+pub const DEFAULT_EPOCHS: usize = 1000;
+
+// Adam optimizer, the learning rate is in centipawns
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+// Errors are printed every REPORT_INTERVAL epochs
+const REPORT_INTERVAL: usize = 50;
+
+static PIECE_NAMES: [&str; 6] = ["QUEEN", "ROOK", "BISHOP", "KNIGHT", "PAWN", "KING"];
+
+struct TuningPosition {
+    // The evaluation from white point of view is offset + sum of coefficient * parameter
+    coefficients: Vec<(usize, f64)>,
+    // Evaluation terms that are not tuned
+    offset: f64,
+    // 1 for a white win, 0.5 for a draw and 0 for a black win
+    result: f64,
+}
+
+impl TuningPosition {
+    fn new(board: &Board, result: f64, pawn_table: &mut PawnHashTable) -> Self {
+        let phase = board.evaluation.phase() as f64 / MAX_PHASE as f64;
+        let mut coefficients = Vec::with_capacity(4 * board.occupancy().count_ones() as usize);
+        for sq in 0..64 {
+            if let Some(piece) = board.squares[sq as usize] {
+                let color = if board.pieces[WHITE].has(sq) { WHITE } else { BLACK };
+                let sign = if color == WHITE { 1.0 } else { -1.0 };
+                let (value_index, square_index) = parameter_indices(piece, sq, color);
+                for index in [value_index, square_index] {
+                    coefficients.push((index, sign * phase));
+                    coefficients.push((index + PARAMETERS_PER_PHASE, sign * (1.0 - phase)));
+                }
+            }
+        }
+
+        let score = evaluate(board, pawn_table);
+        let white_score = if board.to_move == WHITE { score } else { -score };
+        let offset = (white_score - board.evaluation.score(WHITE)) as f64;
+        TuningPosition { coefficients, offset, result }
+    }
+
+    fn evaluate(&self, parameters: &[f64]) -> f64 {
+        self.offset + self.coefficients.iter().map(|&(index, coefficient)| coefficient * parameters[index]).sum::<f64>()
+    }
+}
+
+// Expected result for white from the evaluation
+fn sigmoid(k: f64, evaluation: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * evaluation / 400.0))
+}
+
+fn mean_squared_error(positions: &[TuningPosition], parameters: &[f64], k: f64) -> f64 {
+    positions.iter().map(|position| (position.result - sigmoid(k, position.evaluate(parameters))).powi(2)).sum::<f64>() / positions.len() as f64
+}
+
+// The error is convex in K so a ternary search finds the scaling that best fits the current evaluation
+fn fit_k(positions: &[TuningPosition], parameters: &[f64]) -> f64 {
+    let (mut low, mut high) = (0.0, 10.0);
+    for _ in 0..100 {
+        let third = (high - low) / 3.0;
+        if mean_squared_error(positions, parameters, low + third) < mean_squared_error(positions, parameters, high - third) {
+            high -= third;
+        } else {
+            low += third;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn gradient(positions: &[TuningPosition], parameters: &[f64], k: f64) -> Vec<f64> {
+    let mut gradient = vec![0.0; PARAMETER_COUNT];
+    for position in positions {
+        let sigmoid = sigmoid(k, position.evaluate(parameters));
+        // Derivative of the squared error with respect to the evaluation
+        let derivative = -2.0 * (position.result - sigmoid) * sigmoid * (1.0 - sigmoid) * k * 10f64.ln() / 400.0;
+        for &(index, coefficient) in &position.coefficients {
+            gradient[index] += derivative * coefficient;
+        }
+    }
+    gradient.iter_mut().for_each(|value| *value /= positions.len() as f64);
+    gradient
+}
+
+// Returns the tuned parameters, K is fitted once on the initial parameters
+fn tune_parameters(positions: &[TuningPosition], initial: &[i16], epochs: usize) -> Vec<i16> {
+    let mut parameters: Vec<f64> = initial.iter().map(|&value| value as f64).collect();
+    let k = fit_k(positions, &parameters);
+    eprintln!("K = {k:.4}, initial error {:.6}", mean_squared_error(positions, &parameters, k));
+
+    let mut first_moment = vec![0.0; PARAMETER_COUNT];
+    let mut second_moment = vec![0.0; PARAMETER_COUNT];
+    for epoch in 1..=epochs {
+        let gradient = gradient(positions, &parameters, k);
+        for index in 0..PARAMETER_COUNT {
+            first_moment[index] = BETA1 * first_moment[index] + (1.0 - BETA1) * gradient[index];
+            second_moment[index] = BETA2 * second_moment[index] + (1.0 - BETA2) * gradient[index].powi(2);
+            let first_moment = first_moment[index] / (1.0 - BETA1.powi(epoch as i32));
+            let second_moment = second_moment[index] / (1.0 - BETA2.powi(epoch as i32));
+            parameters[index] -= LEARNING_RATE * first_moment / (second_moment.sqrt() + EPSILON);
+        }
+
+        if epoch % REPORT_INTERVAL == 0 || epoch == epochs {
+            eprintln!("epoch {epoch}, error {:.6}", mean_squared_error(positions, &parameters, k));
+        }
+    }
+
+    parameters.iter().map(|&value| value.round() as i16).collect()
+}
+
+// A lone 1 or 0 would be mistaken for the fullmove number
+fn parse_result(token: &str) -> Option<f64> {
+    match token.trim_matches(|c| matches!(c, '"' | '[' | ']' | ';' | '(' | ')')) {
+        "1-0" | "1.0" => Some(1.0),
+        "0-1" | "0.0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+// Extra tokens between the fen and the result (like EPD opcodes) are ignored
+fn parse_position(line: &str) -> Option<(Board, f64)> {
+    let tokens: Vec<&str> = line.split_ascii_whitespace().collect();
+    let result = parse_result(tokens.last()?)?;
+    let fen_tokens = &tokens[..tokens.len() - 1];
+    let board = Board::from_fen(&fen_tokens[..fen_tokens.len().min(6)].join(" "))
+        .or_else(|_| Board::from_fen(&fen_tokens[..fen_tokens.len().min(4)].join(" ")))
+        .ok()?;
+    Some((board, result))
+}
+
+// Same layout as the tables in evaluation.rs
+fn rust_source(parameters: &[i16]) -> String {
+    let mut source = String::new();
+    for (phase_parameters, suffix) in parameters.chunks_exact(PARAMETERS_PER_PHASE).zip(["MIDDLE", "END"]) {
+        let (piece_values, square_tables) = phase_parameters.split_at(6);
+        let piece_values: Vec<String> = piece_values.iter().map(i16::to_string).collect();
+        source += &format!("static PIECE_VALUE_{suffix}: [i16; 6] = [{}];\n\n", piece_values.join(", "));
+
+        for (table, name) in square_tables.chunks_exact(64).zip(PIECE_NAMES) {
+            source += &format!("static {name}_SQUARE_TABLE_{suffix}: [i16; 64] = [\n");
+            for rank in table.chunks_exact(8) {
+                let rank: Vec<String> = rank.iter().map(|value| format!("{value:3}")).collect();
+                source += &format!("    {},\n", rank.join(","));
+            }
+            source += "];\n\n";
+        }
+    }
+    source
+}
+
+// tune <positions file> [epochs] [output file], the output is written on stdout by default
+pub fn run(mut args: impl Iterator<Item = String>) -> io::Result<()> {
+    let Some(path) = args.next() else {
+        eprintln!("usage: yace tune <positions file> [epochs] [output file]");
+        return Ok(());
+    };
+    let epochs = args.next().and_then(|epochs| epochs.parse().ok()).unwrap_or(DEFAULT_EPOCHS);
+    let output = args.next();
+
+    let mut pawn_table = PawnHashTable::new();
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+    for line in fs::read_to_string(&path)?.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_position(line) {
+            Some((board, result)) => positions.push(TuningPosition::new(&board, result, &mut pawn_table)),
+            None => skipped += 1,
+        }
+    }
+    eprintln!("{} positions loaded, {skipped} lines skipped", positions.len());
+    if positions.is_empty() {
+        return Ok(());
+    }
+
+    let source = rust_source(&tune_parameters(&positions, &parameters(), epochs));
+    match output {
+        Some(output) => fs::write(output, source),
+        None => io::stdout().write_all(source.as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_position() {
+        let (board, result) = parse_position("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 [0.5]").unwrap();
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        assert_eq!(result, 0.5);
+
+        // EPD with an opcode before the result
+        let (board, result) = parse_position("4k3/8/8/8/8/8/4P3/4K3 w - - c9 \"1-0\";").unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        assert_eq!(result, 1.0);
+
+        assert_eq!(parse_position("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 0-1").unwrap().1, 0.0);
+        assert!(parse_position("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").is_none());
+        assert!(parse_position("not a fen 1-0").is_none());
+    }
+
+    #[test]
+    fn test_linear_evaluation() {
+        // The linear model with the current parameters is the incremental evaluation, up to rounding
+        let mut pawn_table = PawnHashTable::new();
+        let parameters: Vec<f64> = parameters().iter().map(|&value| value as f64).collect();
+        for fen in ["r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4", "8/5k2/3p4/8/2B5/8/5PPP/6K1 b - - 0 40"] {
+            let board = Board::from_fen(fen).unwrap();
+            let position = TuningPosition::new(&board, 0.5, &mut pawn_table);
+            let score = evaluate(&board, &mut pawn_table);
+            let white_score = if board.to_move == WHITE { score } else { -score };
+            assert!((position.evaluate(&parameters) - white_score as f64).abs() <= 1.0, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_tuning() {
+        // Being up a knight always wins while being up a pawn is only a draw
+        // so the knight should be worth more and the pawn less in the endgame
+        let mut pawn_table = PawnHashTable::new();
+        let positions: Vec<TuningPosition> = [
+            ("4k3/8/8/8/8/2N5/8/4K3 w - - 0 1", 1.0),
+            ("4k3/8/8/8/8/8/3N4/4K3 b - - 0 1", 1.0),
+            ("4k3/8/8/3N4/8/8/8/4K3 w - - 0 1", 1.0),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", 0.5),
+            ("4k3/8/8/8/8/2P5/8/4K3 b - - 0 1", 0.5),
+            ("4k3/8/8/8/6P1/8/8/4K3 w - - 0 1", 0.5),
+        ].into_iter().map(|(fen, result)| TuningPosition::new(&Board::from_fen(fen).unwrap(), result, &mut pawn_table)).collect();
+        let initial = parameters();
+        let tuned = tune_parameters(&positions, &initial, 20);
+        let (knight, pawn) = (usize::from(KNIGHT) + PARAMETERS_PER_PHASE, usize::from(PAWN) + PARAMETERS_PER_PHASE);
+        assert!(tuned[knight] > initial[knight]);
+        assert!(tuned[pawn] < initial[pawn]);
+
+        let source = rust_source(&tuned);
+        assert!(source.starts_with("static PIECE_VALUE_MIDDLE: [i16; 6] = [900, 500, 330,"));
+        assert!(source.contains("static KING_SQUARE_TABLE_END: [i16; 64] = [\n"));
+    }
+}
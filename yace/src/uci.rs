@@ -0,0 +1,425 @@
+use std::{io::{self, BufRead}, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread::{self, JoinHandle}, time::{Duration, Instant}};
+
+use crate::{board::*, book::{BookSelection, OpeningBook}, eval_trace::evaluation_report, nnue::Network, search::{SearchResult, Searcher, MATE_BOUND, MATE_SCORE, MAX_DEPTH}, syzygy::Tablebases, time_manager::{TimeControl, TimeManager, DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS}, transposition::{TranspositionTable, DEFAULT_HASH_SIZE_MB, MAX_HASH_SIZE_MB}};
+
+const ENGINE_NAME: &str = "yace";
+const ENGINE_AUTHOR: &str = "barollet";
+
+pub struct Uci {
+    board: Board,
+    // Only locked by the search thread while it is running
+    tt: Arc<Mutex<TranspositionTable>>,
+    stop: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<()>>,
+    // Time lost on each move communicating with the GUI, in milliseconds
+    move_overhead: u64,
+    // Replaces the classical evaluation when set
+    network: Option<Arc<Network>>,
+    // The book is only probed at the root when own_book is set
+    book: Option<OpeningBook>,
+    own_book: bool,
+    book_selection: BookSelection,
+    tablebases: Option<Arc<Tablebases>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct GoParams {
+    depth: Option<u8>,
+    movetime: Option<u64>,
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    winc: Option<u64>,
+    binc: Option<u64>,
+    movestogo: Option<u64>,
+    infinite: bool,
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        Uci {
+            board: Board::new(),
+            tt: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_HASH_SIZE_MB))),
+            stop: Arc::new(AtomicBool::new(false)),
+            search_thread: None,
+            move_overhead: DEFAULT_MOVE_OVERHEAD_MS,
+            network: None,
+            book: None,
+            own_book: false,
+            book_selection: BookSelection::Weighted,
+            tablebases: None,
+        }
+    }
+
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let Ok(line) = line else { break };
+            if !self.handle_command(&line) {
+                break;
+            }
+        }
+        self.stop_search();
+    }
+
+    // Returns false when the engine should quit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_ascii_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                println!("id name {ENGINE_NAME}");
+                println!("id author {ENGINE_AUTHOR}");
+                println!("option name Hash type spin default {DEFAULT_HASH_SIZE_MB} min 1 max {MAX_HASH_SIZE_MB}");
+                println!("option name Clear Hash type button");
+                println!("option name Move Overhead type spin default {DEFAULT_MOVE_OVERHEAD_MS} min 0 max {MAX_MOVE_OVERHEAD_MS}");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!("option name BookSelection type combo default Weighted var Weighted var Best");
+                println!("option name SyzygyPath type string default <empty>");
+                println!("uciok");
+            },
+            Some("isready") => println!("readyok"),
+            Some("setoption") => {
+                self.stop_search();
+                self.set_option(tokens);
+            },
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.board = Board::new();
+                self.board.set_network(self.network.clone());
+                self.tt.lock().unwrap().clear();
+            },
+            Some("position") => {
+                self.stop_search();
+                self.set_position(tokens);
+            },
+            Some("go") => {
+                self.stop_search();
+                self.go(GoParams::parse(tokens));
+            },
+            Some("stop") => self.stop_search(),
+            Some("d") => self.display(),
+            Some("eval") => print!("{}", evaluation_report(&self.board)),
+            Some("quit") => return false,
+            _ => (),
+        }
+        true
+    }
+
+    fn set_option<'a>(&mut self, tokens: impl Iterator<Item = &'a str>) {
+        // Option names can contain spaces: "setoption name Clear Hash"
+        let tokens: Vec<&str> = tokens.collect();
+        let value_index = tokens.iter().position(|&token| token == "value").unwrap_or(tokens.len());
+        let name = tokens[..value_index].iter().skip_while(|&&token| token == "name").copied().collect::<Vec<_>>().join(" ");
+        let value = tokens.get(value_index+1..).map(|value| value.join(" ")).unwrap_or_default();
+
+        match name.to_lowercase().as_str() {
+            "hash" => match value.parse() {
+                Ok(size_mb) => {
+                    let mut tt = self.tt.lock().unwrap();
+                    tt.resize(size_mb);
+                    println!("info string Hash set to {} MB", tt.size_mb());
+                },
+                Err(_) => println!("info string invalid Hash value {value}"),
+            },
+            "clear hash" => self.tt.lock().unwrap().clear(),
+            "move overhead" => match value.parse::<u64>() {
+                Ok(move_overhead) => self.move_overhead = move_overhead.min(MAX_MOVE_OVERHEAD_MS),
+                Err(_) => println!("info string invalid Move Overhead value {value}"),
+            },
+            "evalfile" => {
+                if value.is_empty() || value == "<empty>" {
+                    self.network = None;
+                    println!("info string using the classical evaluation");
+                } else {
+                    match Network::load(&value) {
+                        Ok(network) => {
+                            self.network = Some(Arc::new(network));
+                            println!("info string loaded network {value}");
+                        },
+                        Err(err) => println!("info string {err}"),
+                    }
+                }
+                self.board.set_network(self.network.clone());
+            },
+            "ownbook" => self.own_book = value == "true",
+            "bookfile" => {
+                if value.is_empty() || value == "<empty>" {
+                    self.book = None;
+                } else {
+                    match OpeningBook::load(&value) {
+                        Ok(book) => {
+                            println!("info string loaded book {value} with {} entries", book.len());
+                            self.book = Some(book);
+                        },
+                        Err(err) => println!("info string {err}"),
+                    }
+                }
+            },
+            "bookselection" => match value.to_lowercase().as_str() {
+                "best" => self.book_selection = BookSelection::Best,
+                "weighted" => self.book_selection = BookSelection::Weighted,
+                _ => println!("info string invalid BookSelection value {value}"),
+            },
+            "syzygypath" => {
+                self.tablebases = None;
+                if !value.is_empty() && value != "<empty>" {
+                    let tablebases = Tablebases::new(&value);
+                    if tablebases.len() == 0 {
+                        println!("info string no tablebase found in {value}");
+                    } else {
+                        println!("info string found {} tablebases up to {} pieces", tablebases.len(), tablebases.max_pieces());
+                        self.tablebases = Some(Arc::new(tablebases));
+                    }
+                }
+            },
+            _ => println!("info string unknown option {name}"),
+        }
+    }
+
+    fn set_position<'a>(&mut self, mut tokens: impl Iterator<Item = &'a str>) {
+        let board = match tokens.next() {
+            Some("startpos") => Board::new(),
+            Some("fen") => {
+                let fen: Vec<&str> = tokens.by_ref().take_while(|&token| token != "moves").collect();
+                match Board::from_fen(&fen.join(" ")) {
+                    Ok(board) => board,
+                    Err(err) => {
+                        println!("info string invalid fen: {err}");
+                        return;
+                    },
+                }
+            },
+            _ => return,
+        };
+        self.board = board;
+        self.board.set_network(self.network.clone());
+
+        // After a fen the "moves" token has already been consumed by take_while
+        for token in tokens.skip_while(|&token| token == "moves") {
+            match self.board.parse_uci_move(token) {
+                Ok(m) => { self.board.make(m); },
+                Err(err) => {
+                    println!("info string {err}");
+                    return;
+                },
+            }
+        }
+    }
+
+    fn display(&self) {
+        self.board.display();
+        println!("Fen: {}", self.board.to_fen());
+        let legal_moves: Vec<String> = self.board.legal_move_gen().into_iter().map(|m| self.board.to_san(m)).collect();
+        println!("Checkers: {}", if self.board.in_check() {"yes"} else {"no"});
+        println!("Result: {:?}", self.board.game_result());
+        println!("Legal moves: {}", legal_moves.join(" "));
+    }
+
+    fn go(&mut self, params: GoParams) {
+        self.stop.store(false, Ordering::Relaxed);
+
+        // An infinite search is an analysis so the book is not used
+        if let Some(book_move) = self.book_move(&params) {
+            println!("info string book move");
+            println!("bestmove {}", book_move.to_uci());
+            return;
+        }
+
+        let board = self.board.clone();
+        let tt = self.tt.clone();
+        let stop = self.stop.clone();
+        let move_overhead = self.move_overhead;
+        let tablebases = self.tablebases.clone();
+        self.search_thread = Some(thread::spawn(move || run_search(board, &tt, tablebases, params, move_overhead, stop)));
+    }
+
+    fn book_move(&self, params: &GoParams) -> Option<Move> {
+        if !self.own_book || params.infinite {
+            return None;
+        }
+        self.book.as_ref()?.probe(&self.board, self.book_selection, &mut rand::rng())
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            handle.join().expect("Search thread panicked");
+        }
+    }
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn run_search(mut board: Board, tt: &Mutex<TranspositionTable>, tablebases: Option<Arc<Tablebases>>, params: GoParams, move_overhead: u64, stop: Arc<AtomicBool>) {
+    let start = Instant::now();
+    let time_manager = TimeManager::new(start, params.time_control(board.to_move), move_overhead);
+    let max_depth = params.depth.unwrap_or(MAX_DEPTH);
+
+    let mut tt = tt.lock().unwrap();
+    let result = Searcher::new(&mut board, &mut tt, stop.clone())
+        .with_time_manager(time_manager)
+        .with_info_callback(|result| print_info(result, start))
+        .with_tablebases(tablebases.as_deref())
+        .search(max_depth);
+
+    // In infinite mode the best move can only be sent after a stop command
+    if params.infinite {
+        while !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    match (result.best_move, result.ponder_move) {
+        (Some(best_move), Some(ponder_move)) => println!("bestmove {} ponder {}", best_move.to_uci(), ponder_move.to_uci()),
+        (Some(best_move), None) => println!("bestmove {}", best_move.to_uci()),
+        (None, _) => println!("bestmove 0000"),
+    }
+}
+
+fn print_info(result: &SearchResult, start: Instant) {
+    let elapsed = start.elapsed().as_millis().max(1) as u64;
+    let pv: Vec<String> = result.pv.iter().map(|m| m.to_uci()).collect();
+    println!("info depth {} seldepth {} score {} nodes {} nps {} hashfull {} tbhits {} time {elapsed} pv {}",
+        result.depth, result.seldepth, format_score(result.score), result.nodes, result.nodes * 1000 / elapsed, result.hashfull, result.tbhits, pv.join(" "));
+}
+
+// Mate scores are given in moves, negative when the engine is getting mated
+fn format_score(score: i16) -> String {
+    if score >= MATE_BOUND {
+        format!("mate {}", (MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("mate -{}", (MATE_SCORE + score) / 2)
+    } else {
+        format!("cp {score}")
+    }
+}
+
+impl GoParams {
+    fn parse<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Self {
+        let mut params = GoParams::default();
+        while let Some(token) = tokens.next() {
+            match token {
+                "infinite" => params.infinite = true,
+                "depth" => params.depth = tokens.next().and_then(|t| t.parse().ok()),
+                "movetime" => params.movetime = tokens.next().and_then(|t| t.parse().ok()),
+                "wtime" => params.wtime = tokens.next().and_then(|t| t.parse().ok()),
+                "btime" => params.btime = tokens.next().and_then(|t| t.parse().ok()),
+                "winc" => params.winc = tokens.next().and_then(|t| t.parse().ok()),
+                "binc" => params.binc = tokens.next().and_then(|t| t.parse().ok()),
+                "movestogo" => params.movestogo = tokens.next().and_then(|t| t.parse().ok()),
+                _ => (),
+            }
+        }
+        params
+    }
+
+    // An infinite search has no time limit even if clock times are given
+    fn time_control(&self, color: Color) -> TimeControl {
+        if self.infinite {
+            return TimeControl::default();
+        }
+
+        let (time, increment) = if color == WHITE {(self.wtime, self.winc)} else {(self.btime, self.binc)};
+        TimeControl {
+            time,
+            increment: increment.unwrap_or(0),
+            moves_to_go: self.movestogo,
+            movetime: self.movetime,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{encode_move, BookEntry};
+
+    #[test]
+    fn test_go_params() {
+        let params = GoParams::parse("wtime 60000 btime 30000 winc 1000 binc 500 movestogo 10".split_ascii_whitespace());
+        assert_eq!(params.wtime, Some(60000));
+        assert_eq!(params.binc, Some(500));
+        assert_eq!(params.time_control(WHITE), TimeControl { time: Some(60000), increment: 1000, moves_to_go: Some(10), movetime: None });
+        assert_eq!(params.time_control(BLACK), TimeControl { time: Some(30000), increment: 500, moves_to_go: Some(10), movetime: None });
+
+        let params = GoParams::parse("depth 5".split_ascii_whitespace());
+        assert_eq!(params.depth, Some(5));
+        assert_eq!(params.time_control(WHITE), TimeControl::default());
+
+        let params = GoParams::parse("movetime 100".split_ascii_whitespace());
+        assert_eq!(params.time_control(BLACK).movetime, Some(100));
+
+        let params = GoParams::parse("infinite wtime 1000".split_ascii_whitespace());
+        assert_eq!(params.time_control(WHITE), TimeControl::default());
+    }
+
+    #[test]
+    fn test_format_score() {
+        assert_eq!(format_score(35), "cp 35");
+        assert_eq!(format_score(-200), "cp -200");
+        assert_eq!(format_score(MATE_SCORE - 1), "mate 1");
+        assert_eq!(format_score(MATE_SCORE - 3), "mate 2");
+        assert_eq!(format_score(-MATE_SCORE + 2), "mate -1");
+        assert_eq!(format_score(-MATE_SCORE + 4), "mate -2");
+    }
+
+    #[test]
+    fn test_position() {
+        let mut uci = Uci::new();
+        uci.handle_command("position startpos moves e2e4 e7e5 g1f3");
+        assert_eq!(uci.board.to_move, BLACK);
+        assert_eq!(uci.board.squares[F3 as usize], Some(KNIGHT));
+
+        uci.handle_command("position fen 4k3/P7/8/8/8/8/8/4K3 w - - 0 1 moves a7a8q");
+        assert_eq!(uci.board.squares[A8 as usize], Some(QUEEN));
+
+        // Only long algebraic moves are accepted, the moves stop at the first other one
+        uci.handle_command("position startpos moves e2e4 e5 g8f6");
+        assert_eq!(uci.board.to_move, BLACK);
+        assert_eq!(uci.board.squares[E7 as usize], Some(PAWN));
+    }
+
+    #[test]
+    fn test_set_option() {
+        let mut uci = Uci::new();
+        uci.handle_command("setoption name Hash value 1");
+        assert_eq!(uci.tt.lock().unwrap().size_mb(), 1);
+        uci.handle_command("setoption name Clear Hash");
+        uci.handle_command("setoption name Hash value 8");
+        assert_eq!(uci.tt.lock().unwrap().size_mb(), 8);
+        uci.handle_command("setoption name Move Overhead value 50");
+        assert_eq!(uci.move_overhead, 50);
+
+        // A missing network file keeps the classical evaluation
+        uci.handle_command("setoption name EvalFile value /nonexistent/network.bin");
+        assert!(uci.network.is_none() && uci.board.nnue.is_none());
+
+        uci.handle_command("setoption name OwnBook value true");
+        uci.handle_command("setoption name BookSelection value Best");
+        assert!(uci.own_book);
+        assert_eq!(uci.book_selection, BookSelection::Best);
+        uci.handle_command("setoption name BookFile value /nonexistent/book.bin");
+        assert!(uci.book.is_none());
+        uci.handle_command("setoption name SyzygyPath value /nonexistent/syzygy");
+        assert!(uci.tablebases.is_none());
+    }
+
+    #[test]
+    fn test_book_move() {
+        let mut uci = Uci::new();
+        let e4 = Board::new().parse_uci_move("e2e4").unwrap();
+        uci.book = Some(OpeningBook::from_entries(vec![BookEntry { key: Board::new().polyglot_key(), raw_move: encode_move(&Board::new(), e4), weight: 1, learn: 0 }]));
+        assert_eq!(uci.book_move(&GoParams::default()), None);
+
+        uci.own_book = true;
+        assert_eq!(uci.book_move(&GoParams::default()), Some(e4));
+        assert_eq!(uci.book_move(&GoParams::parse("infinite".split_ascii_whitespace())), None);
+        uci.handle_command("position startpos moves e2e4");
+        assert_eq!(uci.book_move(&GoParams::default()), None);
+    }
+}